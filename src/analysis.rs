@@ -0,0 +1,262 @@
+// Post-game analysis, find the moves that changed the theoretical outcome of a game
+
+use std::fmt;
+
+use crate::position::{Position, WIDTH};
+use crate::record::GameRecord;
+use crate::score::Score;
+use crate::solver::Solver;
+
+// Half width of the evaluation graph
+const GRAPH_WIDTH: i32 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Classification {
+    Best,
+    /// same outcome but a slower win or a faster loss
+    Inaccuracy,
+    /// a win turned into a draw
+    Mistake,
+    /// a win or a draw turned into a loss
+    Blunder,
+}
+
+impl Classification {
+    fn new(best_score: Score, score: Score) -> Classification {
+        debug_assert!(score.value() <= best_score.value());
+        if score.outcome() == best_score.outcome() {
+            if score == best_score {
+                Classification::Best
+            } else {
+                Classification::Inaccuracy
+            }
+        } else if score.is_loss() {
+            Classification::Blunder
+        } else {
+            Classification::Mistake
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Classification::Best => "",
+            Classification::Inaccuracy => "?!",
+            Classification::Mistake => "?",
+            Classification::Blunder => "??",
+        }
+    }
+}
+
+impl fmt::Display for Classification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Classification::Best => "best",
+            Classification::Inaccuracy => "inaccuracy",
+            Classification::Mistake => "mistake",
+            Classification::Blunder => "blunder",
+        })
+    }
+}
+
+// Scores are from the point of view of the player who made the move
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MoveAnalysis {
+    pub ply: usize,
+    pub column: u64,
+    pub score: Score,
+    pub best_score: Score,
+    pub best_columns: Vec<u64>,
+    pub classification: Classification,
+}
+
+impl MoveAnalysis {
+    pub fn first_player(&self) -> bool {
+        self.ply.is_multiple_of(2)
+    }
+
+    // Score of the position after the move from the point of view of the first player
+    pub fn absolute_score(&self) -> i32 {
+        self.score.absolute()
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameAnalysis {
+    pub moves: Vec<MoveAnalysis>,
+}
+
+// Solve every position of the game starting at ply `from`,
+// the positions of the opening are very expensive to solve
+pub fn analyze_game(
+    solver: &mut Solver,
+    moves: &[u64],
+    from: usize,
+) -> Result<GameAnalysis, String> {
+    let mut p = Position::new();
+    let mut analysis = Vec::new();
+    for (ply, &column) in moves.iter().enumerate() {
+        let next = p
+            .try_play(column)
+            .map_err(|e| format!("{} at ply {}", e, ply + 1))?;
+        if ply >= from {
+            let scores = solver.analyze(&p);
+            let best_score = scores
+                .iter()
+                .flatten()
+                .copied()
+                .max_by_key(Score::value)
+                .unwrap();
+            let score = scores[column as usize].unwrap();
+            analysis.push(MoveAnalysis {
+                ply,
+                column,
+                score,
+                best_score,
+                best_columns: (0..WIDTH)
+                    .filter(|&c| scores[c as usize] == Some(best_score))
+                    .collect(),
+                classification: Classification::new(best_score, score),
+            });
+        }
+        p = next;
+    }
+    Ok(GameAnalysis { moves: analysis })
+}
+
+impl GameAnalysis {
+    // Store the scores and the errors in the record
+    pub fn annotate(&self, record: &mut GameRecord) {
+        for m in &self.moves {
+            let annotated = match record.moves.get_mut(m.ply) {
+                Some(annotated) => annotated,
+                None => continue,
+            };
+            annotated.eval = Some(m.score);
+            if m.classification != Classification::Best {
+                annotated.comment = Some(format!(
+                    "{}, best was {}",
+                    m.classification,
+                    best_columns_string(&m.best_columns)
+                ));
+            }
+        }
+    }
+}
+
+fn best_columns_string(columns: &[u64]) -> String {
+    columns
+        .iter()
+        .map(|c| (c + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+// One line per move with the score of the first player as an horizontal bar
+impl fmt::Display for GameAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let max_score = self
+            .moves
+            .iter()
+            .map(|m| m.absolute_score().abs())
+            .max()
+            .unwrap_or(1)
+            .max(1);
+        for m in &self.moves {
+            let score = m.absolute_score();
+            // round up so that any non draw score is visible
+            let len = ((score.abs() * GRAPH_WIDTH + max_score - 1) / max_score) as usize;
+            let (left, right) = if score < 0 {
+                ("#".repeat(len), String::new())
+            } else {
+                (String::new(), "#".repeat(len))
+            };
+            let best = if m.classification == Classification::Best {
+                String::new()
+            } else {
+                format!(
+                    "{} {}, best {}",
+                    m.classification.symbol(),
+                    m.classification,
+                    best_columns_string(&m.best_columns)
+                )
+            };
+            writeln!(
+                f,
+                "{:3}. {} {}  {:+3} {:>w$}|{:<w$} {}",
+                m.ply + 1,
+                if m.first_player() { "first " } else { "second" },
+                m.column + 1,
+                score,
+                left,
+                right,
+                best,
+                w = GRAPH_WIDTH as usize
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::parse_moves;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_classification() {
+        let classify =
+            |best, score| Classification::new(Score::new(best, 10), Score::new(score, 10));
+        assert_eq!(classify(3, 3), Classification::Best);
+        assert_eq!(classify(0, 0), Classification::Best);
+        assert_eq!(classify(3, 1), Classification::Inaccuracy);
+        assert_eq!(classify(-1, -4), Classification::Inaccuracy);
+        assert_eq!(classify(3, 0), Classification::Mistake);
+        assert_eq!(classify(3, -2), Classification::Blunder);
+        assert_eq!(classify(0, -2), Classification::Blunder);
+    }
+
+    #[test]
+    fn test_analyze_game() {
+        // the player to move can only win by playing in the 4th column,
+        // the second move lets the opponent win
+        let moves = parse_moves("612575172312315153714224").unwrap();
+        let mut solver = Solver::new();
+        let analysis = analyze_game(&mut solver, &moves, 22).unwrap();
+        assert_eq!(analysis.moves.len(), 2);
+
+        let m = &analysis.moves[0];
+        assert_eq!((m.ply, m.column), (22, 1));
+        assert_eq!(m.best_columns, vec![3]);
+        assert!(m.best_score.is_win());
+        assert_eq!(m.classification, Classification::Blunder);
+
+        let m = &analysis.moves[1];
+        assert_eq!(m.best_columns, vec![3]);
+        assert_eq!(m.classification, Classification::Best);
+        assert!(m.absolute_score() < 0);
+
+        let mut record = GameRecord::new();
+        record.moves = moves.iter().map(|&c| crate::record::Move::new(c)).collect();
+        analysis.annotate(&mut record);
+        assert_eq!(
+            record.moves[22].comment.as_deref(),
+            Some("blunder, best was 4")
+        );
+        assert_eq!(record.moves[23].eval, Some(m.score));
+
+        let report = analysis.to_string();
+        assert_eq!(report.lines().count(), 2);
+        assert!(report
+            .lines()
+            .next()
+            .unwrap()
+            .ends_with("?? blunder, best 4"));
+
+        assert!(analyze_game(&mut solver, &[0, 0, 0, 0, 0, 0, 0], 10).is_err());
+        let winning = Position::from_str("1212121").unwrap();
+        assert!(winning.opponent().is_winning());
+        assert!(analyze_game(&mut solver, &[0, 1, 0, 1, 0, 1, 0, 1], 8).is_err());
+    }
+}
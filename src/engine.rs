@@ -0,0 +1,333 @@
+// UCI like protocol to drive the solver from a GUI or an arena
+//
+// commands:
+//   uci                                      -> id, options and uciok
+//   isready                                  -> readyok
+//   ucinewgame                               clear the transposition table
+//   setoption name <name> [value <value>]
+//   position startpos [moves <moves>]        moves as in the data files ("4453" or "4 4 5 3")
+//   go [movetime <ms> | infinite]            -> info lines and bestmove
+//   stop                                     abort the search and send bestmove
+//   quit
+//
+// replies:
+//   info currmove <col> score <score> nodes <nodes>     for each solved column
+//   info score <score> nodes <nodes> time <ms> pv <moves>
+//   bestmove <col>
+
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::position::{moves_to_string, Position};
+use crate::score::Score;
+use crate::solver::{Solver, COLUMNS_ORDER};
+
+type Output = Arc<Mutex<dyn Write + Send>>;
+
+pub fn run<R: BufRead, W: Write + Send + 'static>(input: R, output: W) -> io::Result<()> {
+    let mut engine = Engine::new(Arc::new(Mutex::new(output)));
+    for line in input.lines() {
+        if !engine.handle(&line?)? {
+            break;
+        }
+    }
+    engine.stop();
+    Ok(())
+}
+
+struct Engine {
+    out: Output,
+    position: Position,
+    // taken by the search thread while searching
+    solver: Option<Solver>,
+    search: Option<(Arc<AtomicBool>, JoinHandle<Solver>)>,
+}
+
+enum Limit {
+    Solve,
+    MoveTime(Duration),
+    Infinite,
+}
+
+impl Engine {
+    fn new(out: Output) -> Engine {
+        Engine {
+            out,
+            position: Position::new(),
+            solver: Some(Solver::new()),
+            search: None,
+        }
+    }
+
+    // Returns false on quit
+    fn handle(&mut self, line: &str) -> io::Result<bool> {
+        let mut tokens = line.split_ascii_whitespace();
+        match tokens.next() {
+            Some("uci") => {
+                self.send("id name connect4")?;
+                self.send("id author Charles Cabergs")?;
+                self.send("option name Clear Hash type button")?;
+                self.send("uciok")?;
+            }
+            Some("isready") => self.send("readyok")?,
+            Some("ucinewgame") => {
+                self.stop();
+                self.solver().reset();
+                self.position = Position::new();
+            }
+            Some("setoption") => {
+                let option = line.split(" name ").nth(1).map(|rest| {
+                    rest.split(" value ")
+                        .next()
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string()
+                });
+                match option.as_deref() {
+                    Some("Clear Hash") => {
+                        self.stop();
+                        self.solver().reset();
+                    }
+                    _ => self.send(&format!("info string unknown option \"{}\"", line))?,
+                }
+            }
+            Some("position") => {
+                self.stop();
+                let position = match tokens.next() {
+                    Some("startpos") => tokens
+                        .skip_while(|&t| t == "moves")
+                        .collect::<String>()
+                        .parse::<Position>(),
+                    _ => Err("expected \"position startpos\"".to_string()),
+                };
+                match position {
+                    Ok(position) => self.position = position,
+                    Err(e) => self.send(&format!("info string {}", e))?,
+                }
+            }
+            Some("go") => match parse_limit(tokens) {
+                Some(limit) => self.go(limit),
+                None => self.send(&format!("info string bad go command \"{}\"", line))?,
+            },
+            Some("stop") => self.stop(),
+            Some("quit") => return Ok(false),
+            Some(command) => self.send(&format!("info string unknown command \"{}\"", command))?,
+            None => (),
+        }
+        Ok(true)
+    }
+
+    fn go(&mut self, limit: Limit) {
+        self.stop();
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut solver = self.solver.take().unwrap();
+        solver.set_stop_flag(stop.clone());
+        if let Limit::MoveTime(movetime) = limit {
+            let stop = stop.clone();
+            // the flag is only used by this search so it doesn't matter if it's set late
+            thread::spawn(move || {
+                thread::sleep(movetime);
+                stop.store(true, Ordering::Relaxed);
+            });
+        }
+        let position = self.position;
+        let out = self.out.clone();
+        let search_stop = stop.clone();
+        let handle = thread::spawn(move || {
+            let best = search(&mut solver, position, &out);
+            // the protocol doesn't allow to send bestmove before stop in infinite mode
+            if let Limit::Infinite = limit {
+                while !search_stop.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(5));
+                }
+            }
+            let bestmove = match best {
+                Some(c) => format!("bestmove {}", c + 1),
+                None => "bestmove (none)".to_string(),
+            };
+            // nothing to do if the GUI is gone
+            let _ = writeln!(out.lock().unwrap(), "{}", bestmove);
+            solver
+        });
+        self.search = Some((stop, handle));
+    }
+
+    // Abort the current search and wait for its bestmove
+    fn stop(&mut self) {
+        if let Some((stop, handle)) = self.search.take() {
+            stop.store(true, Ordering::Relaxed);
+            self.solver = Some(handle.join().unwrap());
+        }
+    }
+
+    fn solver(&mut self) -> &mut Solver {
+        self.solver.as_mut().unwrap()
+    }
+
+    fn send(&self, line: &str) -> io::Result<()> {
+        let mut out = self.out.lock().unwrap();
+        writeln!(out, "{}", line)?;
+        out.flush()
+    }
+}
+
+fn parse_limit<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Option<Limit> {
+    match tokens.next() {
+        None => Some(Limit::Solve),
+        Some("infinite") => Some(Limit::Infinite),
+        Some("movetime") => {
+            let ms = tokens.next()?.parse().ok()?;
+            Some(Limit::MoveTime(Duration::from_millis(ms)))
+        }
+        _ => None,
+    }
+}
+
+// Solve each column until the search is finished or stopped, returns the best column found
+fn search(solver: &mut Solver, p: Position, out: &Output) -> Option<u64> {
+    let begin = Instant::now();
+    solver.visited = 0;
+    if p.opponent().is_winning() {
+        return None; // the game is already over
+    }
+    let valid: Vec<u64> = COLUMNS_ORDER
+        .iter()
+        .copied()
+        .filter(|&c| p.is_valid_play(c))
+        .collect();
+    let mut best: Option<(u64, Score)> = None;
+    for &c in &valid {
+        let score = solver.solve_play(&p, c);
+        if solver.stopped() {
+            break;
+        }
+        let _ = writeln!(
+            out.lock().unwrap(),
+            "info currmove {} score {} nodes {}",
+            c + 1,
+            score.value(),
+            solver.visited
+        );
+        if best.is_none_or(|(_, s)| score.value() > s.value()) {
+            best = Some((c, score));
+        }
+    }
+    // no column was solved in time, fallback to the most central one
+    let (best_col, best_score) = match best {
+        Some(best) => best,
+        None => return valid.first().copied(),
+    };
+    let mut pv = vec![best_col];
+    if !solver.stopped() {
+        pv.extend(solver.principal_variation(p.play(best_col)));
+    }
+    let _ = writeln!(
+        out.lock().unwrap(),
+        "info score {} nodes {} time {} pv {}",
+        best_score.value(),
+        solver.visited,
+        begin.elapsed().as_millis(),
+        moves_to_string(&pv)
+    );
+    Some(best_col)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn lines(&self) -> Vec<String> {
+            let buf = self.0.lock().unwrap();
+            String::from_utf8_lossy(&buf)
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    fn engine() -> (Engine, SharedBuf) {
+        let buf = SharedBuf::default();
+        (Engine::new(Arc::new(Mutex::new(buf.clone()))), buf)
+    }
+
+    // Wait for the end of the search without stopping it
+    fn wait(engine: &mut Engine) {
+        let (_, handle) = engine.search.take().unwrap();
+        engine.solver = Some(handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_handshake() {
+        let (mut engine, buf) = engine();
+        assert!(engine.handle("uci").unwrap());
+        assert!(engine.handle("isready").unwrap());
+        assert!(!engine.handle("quit").unwrap());
+        let lines = buf.lines();
+        assert_eq!(lines.first().unwrap(), "id name connect4");
+        assert_eq!(&lines[lines.len() - 2..], ["uciok", "readyok"]);
+    }
+
+    // the player to move can only win by playing in the 4th column
+    const PUZZLE: &str = "6125751723123151537142";
+
+    #[test]
+    fn test_go() {
+        let (mut engine, buf) = engine();
+        engine
+            .handle(&format!("position startpos moves {}", PUZZLE))
+            .unwrap();
+        engine.handle("go").unwrap();
+        wait(&mut engine);
+        let lines = buf.lines();
+        assert_eq!(lines.last().unwrap(), "bestmove 4");
+        assert!(lines[lines.len() - 2].starts_with("info score 10 "));
+        assert!(lines[lines.len() - 2].ends_with(" pv 4"));
+    }
+
+    #[test]
+    fn test_go_infinite_waits_for_stop() {
+        let (mut engine, buf) = engine();
+        engine
+            .handle(&format!("position startpos moves {}", PUZZLE))
+            .unwrap();
+        engine.handle("go infinite").unwrap();
+        thread::sleep(Duration::from_millis(20));
+        assert!(!buf.lines().iter().any(|l| l.starts_with("bestmove")));
+        engine.handle("stop").unwrap();
+        assert_eq!(buf.lines().last().unwrap(), "bestmove 4");
+    }
+
+    #[test]
+    fn test_bad_commands() {
+        let (mut engine, buf) = engine();
+        engine
+            .handle(&format!("position startpos moves {}", PUZZLE))
+            .unwrap();
+        engine.handle("position startpos moves 1111111").unwrap();
+        engine.handle("go movetime").unwrap();
+        engine.handle("foo").unwrap();
+        assert_eq!(buf.lines().len(), 3);
+        assert!(buf.lines().iter().all(|l| l.starts_with("info string ")));
+        // the position is left unchanged
+        engine.handle("go").unwrap();
+        wait(&mut engine);
+        assert_eq!(buf.lines().last().unwrap(), "bestmove 4");
+    }
+}
@@ -1,34 +1,110 @@
+use std::env;
 use std::error::Error;
+use std::fs;
 use std::io;
 use std::io::prelude::*;
-use std::str::FromStr;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+pub mod analysis;
+pub mod engine;
+pub mod pns;
 pub mod position;
+pub mod puzzle;
+pub mod random;
+pub mod record;
+pub mod rules;
+pub mod score;
+pub mod server;
 pub mod solver;
+pub mod tablebase;
+pub mod threats;
+pub mod tui;
 
-use position::Position;
+use analysis::analyze_game;
+use pns::ProofNumberSolver;
+use position::{count_positions, moves_to_string, parse_moves, Position};
+use puzzle::PuzzleGenerator;
+use random::RandomPositionGenerator;
+use record::{parse_records, write_records, GameRecord};
+use rules::Rule;
+use score::Score;
 use separator::Separatable;
-use solver::Solver;
+use server::Server;
+use solver::{Outcome, RootStrategy, SearchStats, SharedCache, Solver, SolverConfig};
+use tablebase::Tablebase;
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => bench(&[]),
+        Some("bench") => bench(&args[1..]),
+        Some("puzzles") => puzzles(&args[1..]),
+        Some("engine") => Ok(engine::run(io::stdin().lock(), io::stdout())?),
+        Some("serve") => serve(&args[1..]),
+        Some("play") => Ok(tui::run()?),
+        Some("analyze") => analyze(&args[1..]),
+        Some("perft") => perft(&args[1..]),
+        Some("positions") => positions(&args[1..]),
+        Some("rules") => rules(),
+        Some("weak") => weak(),
+        Some("tablebase") => build_tablebase(&args[1..]),
+        Some(command) => Err(format!("unknown command \"{}\"", command).into()),
+    }
+}
+
+// usage: connect4 play
+// see tui.rs for the keys
+
+// usage: connect4 engine
+// see engine.rs for the protocol
+
+// Solve the positions read on stdin and compare them to the expected score
+// usage: connect4 [bench [--tablebase <file>] [--tt-play] [--killers] [--history] [--etc]
+//                        [--root bisection|mtdf|aspiration|full] [--shared-cache]] < data/Test_L3_R1
+fn bench(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let usage =
+        "usage: connect4 bench [--tablebase <file>] [--tt-play] [--killers] [--history] [--etc] \
+                 [--root bisection|mtdf|aspiration|full] [--shared-cache]";
     let mut total_time = Duration::new(0, 0);
     let mut total_solve = 0;
     let mut total_visited = 0;
-    let mut solver = Solver::new();
-
-    // let mut position = Position::from_str("1212")?;
-    // println!("{position:?}");
-    // for result in io::stdin().lock().lines() {
-    //     let line = result?;
-    //     let col = line.chars().take(1).next().unwrap().to_digit(10).unwrap();
-    //     position = position.play(col.into());
-    //     println!("AI is thinking..");
-    //     let ai_col = solver.best_play(position.clone());
-    //     println!("AI is done thinking and played {ai_col}");
-    //     position = position.play(ai_col.into());
-    //     println!("{:?}", position.clone());
-    // }
+    let mut total_stats = SearchStats::default();
+    let mut config = SolverConfig::default();
+    let mut tablebase = None;
+    let mut shared_cache = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tablebase" => {
+                let file = args.next().ok_or(usage)?;
+                tablebase = Some(Tablebase::read(io::BufReader::new(fs::File::open(file)?))?);
+            }
+            "--tt-play" => config.tt_play = true,
+            "--killers" => config.killers = true,
+            "--history" => config.history = true,
+            "--etc" => config.enhanced_cutoffs = true,
+            "--shared-cache" => shared_cache = true,
+            "--root" => {
+                config.root = match args.next().map(String::as_str) {
+                    Some("bisection") => RootStrategy::Bisection,
+                    Some("mtdf") => RootStrategy::Mtdf,
+                    Some("aspiration") => RootStrategy::Aspiration,
+                    Some("full") => RootStrategy::FullWindow,
+                    _ => return Err(usage.into()),
+                }
+            }
+            _ => return Err(usage.into()),
+        }
+    }
+    let shared_cache = shared_cache.then(SharedCache::new);
+    let mut solver = match &shared_cache {
+        Some(cache) => Solver::with_shared_cache(config, cache.clone()),
+        None => Solver::with_config(config),
+    };
+    if let Some(tablebase) = tablebase {
+        solver.set_tablebase(Arc::new(tablebase));
+    }
 
     for result in io::stdin().lock().lines() {
         let line = result?;
@@ -40,15 +116,14 @@ fn main() -> Result<(), Box<dyn Error>> {
         let expected_score = fields[1].parse::<i32>()?;
         let pos = fields[0].parse::<Position>()?;
         // print!("{:?}", pos);
-        let begin = Instant::now();
-        let score = solver.solve(pos);
-        let elapsed = begin.elapsed();
+        let (score, stats) = solver.solve_with_stats(pos);
+        let score = score.value();
         println!(
             "{:03}: score: {:3}, time: {:>8.2?}, visited {:>10}",
             total_solve,
             score,
-            elapsed,
-            solver.visited.separated_string()
+            stats.elapsed,
+            stats.nodes.separated_string()
         );
         if score != expected_score {
             eprintln!(
@@ -57,16 +132,262 @@ fn main() -> Result<(), Box<dyn Error>> {
             );
         }
         assert_eq!(score, expected_score);
-        total_time += elapsed;
+        total_time += stats.elapsed;
         total_solve += 1;
-        total_visited += solver.visited;
+        total_visited += stats.nodes;
+        total_stats.add(&stats);
         solver.reset();
+        if let Some(cache) = &shared_cache {
+            cache.clear();
+        }
     }
+    println!("{}", total_stats);
     println!(
-        "mean time: {:?} | mean visited {:10}",
+        "mean time: {:?} | mean visited {:10} | {} nodes/s",
         total_time / total_solve,
-        (total_visited / total_solve as usize).separated_string()
+        (total_visited / total_solve as usize).separated_string(),
+        ((total_visited as f64 / total_time.as_secs_f64()) as u64).separated_string()
+    );
+
+    Ok(())
+}
+
+// Print `count` puzzles where the player to move wins in at most `max_win_in` moves
+// usage: connect4 puzzles <max_win_in> [count] [seed]
+fn puzzles(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let max_win_in = match args.first() {
+        Some(arg) => arg.parse::<u64>()?,
+        None => return Err("usage: connect4 puzzles <max_win_in> [count] [seed]".into()),
+    };
+    let count = args.get(1).map_or(Ok(10), |arg| arg.parse::<usize>())?;
+    let seed = args.get(2).map_or(Ok(0), |arg| arg.parse::<u64>())?;
+    let mut solver = Solver::new();
+    let mut generator = PuzzleGenerator::new(max_win_in, seed);
+    for _ in 0..count {
+        println!("{}", generator.generate(&mut solver)?);
+    }
+    Ok(())
+}
+
+// Prove that the second player at least draws the positions read on stdin with the rules of
+// rules.rs and compare with the expected score
+// usage: connect4 rules < data/Test_L1_R1
+fn rules() -> Result<(), Box<dyn Error>> {
+    let (mut total, mut proven, mut wrong) = (0, 0, 0);
+    let mut used = [0; Rule::ALL.len()];
+    for result in io::stdin().lock().lines() {
+        let line = result?;
+        let fields: Vec<&str> = line.split_ascii_whitespace().collect();
+        if fields.len() != 2 {
+            eprintln!("wrong line format {:?}", line);
+            continue;
+        }
+        let p = fields[0].parse::<Position>()?;
+        // score of the first player
+        let score = Score::new(fields[1].parse::<i32>()?, p.play_count()).absolute();
+        total += 1;
+        if let Some(proof) = rules::prove(&p) {
+            proven += 1;
+            if score > 0 {
+                wrong += 1;
+                println!(
+                    "{} is a win for the first player but was proven with",
+                    fields[0]
+                );
+                print!("{}", proof);
+            }
+            for solution in &proof.solutions {
+                used[solution.rule as usize] += 1;
+            }
+        }
+    }
+    println!("{} of {} positions proven, {} wrong", proven, total, wrong);
+    for (rule, count) in Rule::ALL.iter().zip(&used) {
+        println!("{:>14} {}", rule.to_string(), count);
+    }
+    Ok(())
+}
+
+// Weakly solve the positions read on stdin with alpha-beta and with proof-number search,
+// compare their outcomes to the expected scores and their speed
+// usage: connect4 weak < data/Test_L3_R1
+fn weak() -> Result<(), Box<dyn Error>> {
+    let mut solver = Solver::new();
+    let mut pns = ProofNumberSolver::default();
+    let (mut total, mut wrong) = (0, [0; 2]);
+    let (mut times, mut nodes) = ([Duration::new(0, 0); 2], [0; 2]);
+    for result in io::stdin().lock().lines() {
+        let line = result?;
+        let fields: Vec<&str> = line.split_ascii_whitespace().collect();
+        if fields.len() != 2 {
+            eprintln!("wrong line format {:?}", line);
+            continue;
+        }
+        let expected = Outcome::from_score(fields[1].parse::<i32>()?);
+        let p = fields[0].parse::<Position>()?;
+
+        let begin = Instant::now();
+        solver.reset();
+        let alpha_beta = solver.solve_weak(p);
+        times[0] += begin.elapsed();
+        nodes[0] += solver.visited;
+
+        let begin = Instant::now();
+        pns.nodes = 0;
+        let proof_number = pns.solve(&p);
+        times[1] += begin.elapsed();
+        nodes[1] += pns.nodes;
+
+        for (i, outcome) in [alpha_beta, proof_number].iter().enumerate() {
+            if *outcome != expected {
+                wrong[i] += 1;
+                eprintln!("{}: {} instead of {}", fields[0], outcome, expected);
+            }
+        }
+        total += 1;
+    }
+    if total == 0 {
+        return Err("no positions".into());
+    }
+    for (i, name) in ["alpha-beta", "proof-number"].iter().enumerate() {
+        println!(
+            "{:>12}: mean time: {:>10.2?} | mean nodes {:>10} | {} wrong",
+            name,
+            times[i] / total,
+            (nodes[i] / total as usize).separated_string(),
+            wrong[i]
+        );
+    }
+    Ok(())
+}
+
+// Build the tablebase of the positions with at most `max_empty` empty cells reachable from the
+// positions read on stdin, in the format of the data files
+// usage: connect4 tablebase <max_empty> <file> < data/Test_L3_R1
+fn build_tablebase(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (max_empty, file) = match args {
+        [max_empty, file] => (max_empty.parse::<u64>()?, file),
+        _ => return Err("usage: connect4 tablebase <max_empty> <file>".into()),
+    };
+    let mut roots = Vec::new();
+    for result in io::stdin().lock().lines() {
+        let line = result?;
+        match line.split_ascii_whitespace().next() {
+            Some(moves) => roots.push(moves.parse::<Position>()?),
+            None => continue,
+        }
+    }
+    let begin = Instant::now();
+    let tablebase = Tablebase::build(&roots, max_empty)?;
+    tablebase.write(io::BufWriter::new(fs::File::create(file)?))?;
+    println!(
+        "{} positions in {:.2?}",
+        tablebase.len().separated_string(),
+        begin.elapsed()
     );
+    Ok(())
+}
 
+// Print `count` random positions of `plies` moves where the player to move can't win
+// immediately, with their score in the format of the data files if a score range is given
+// usage: connect4 positions <plies> [count] [seed] [min_score max_score]
+fn positions(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let plies = match args.first() {
+        Some(arg) => arg.parse::<u64>()?,
+        None => {
+            return Err(
+                "usage: connect4 positions <plies> [count] [seed] [min_score max_score]".into(),
+            )
+        }
+    };
+    let count = args.get(1).map_or(Ok(10), |arg| arg.parse::<usize>())?;
+    let seed = args.get(2).map_or(Ok(0), |arg| arg.parse::<u64>())?;
+    let mut generator = RandomPositionGenerator::new(plies, seed);
+    generator.no_immediate_win = true;
+    match (args.get(3), args.get(4)) {
+        (Some(min), Some(max)) => {
+            let scores = min.parse::<i32>()?..=max.parse::<i32>()?;
+            let mut solver = Solver::new();
+            for _ in 0..count {
+                let (moves, score) = generator.generate_with_score(&mut solver, scores.clone())?;
+                println!("{} {}", moves_to_string(&moves), score.value());
+            }
+        }
+        _ => {
+            for _ in 0..count {
+                println!("{}", moves_to_string(&generator.generate()?));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Serve the solver over HTTP, see server.rs for the endpoints
+// usage: connect4 serve [address] [workers] [time_limit_ms]
+fn serve(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let addr = args.first().map_or("127.0.0.1:8080", String::as_str);
+    let workers = args.get(1).map_or(Ok(4), |arg| arg.parse::<usize>())?;
+    let mut server = Server::bind(addr, workers)?;
+    if let Some(ms) = args.get(2) {
+        server.set_time_limit(Duration::from_millis(ms.parse()?));
+    }
+    eprintln!("listening on http://{}", server.local_addr()?);
+    Ok(server.run()?)
+}
+
+// Print the evaluation graph and the errors of games given as a move string or a record file,
+// the positions before `from_ply` are skipped since they are expensive to solve
+// usage: connect4 analyze <moves|file> [from_ply] [--annotate]
+fn analyze(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let input = match args.first() {
+        Some(input) => input,
+        None => return Err("usage: connect4 analyze <moves|file> [from_ply] [--annotate]".into()),
+    };
+    let annotate = args.iter().any(|arg| arg == "--annotate");
+    let from = args[1..]
+        .iter()
+        .find(|arg| !arg.starts_with("--"))
+        .map_or(Ok(0), |arg| arg.parse::<usize>())?;
+    let mut records = match parse_moves(input) {
+        Ok(moves) => {
+            let mut record = GameRecord::new();
+            record.moves = moves.into_iter().map(record::Move::new).collect();
+            vec![record]
+        }
+        Err(_) => parse_records(&fs::read_to_string(input)?)?,
+    };
+    let mut solver = Solver::new();
+    for record in &mut records {
+        let analysis = analyze_game(&mut solver, &record.columns(), from)?;
+        if annotate {
+            analysis.annotate(record);
+        } else {
+            println!("{} - {}", record.first, record.second);
+            println!("{}", analysis);
+        }
+    }
+    if annotate {
+        print!("{}", write_records(&records));
+    }
+    Ok(())
+}
+
+// Count the move sequences of each length, or the distinct positions with --distinct,
+// a position and its mirror are counted once with --symmetric
+// usage: connect4 perft <depth> [--distinct | --symmetric]
+fn perft(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let depth = match args.first() {
+        Some(arg) => arg.parse::<u64>()?,
+        None => return Err("usage: connect4 perft <depth> [--distinct | --symmetric]".into()),
+    };
+    let counts = match args.get(1).map(String::as_str) {
+        None => (0..=depth).map(|d| Position::new().perft(d)).collect(),
+        Some("--distinct") => count_positions(depth, false),
+        Some("--symmetric") => count_positions(depth, true),
+        Some(arg) => return Err(format!("unknown option \"{}\"", arg).into()),
+    };
+    for (ply, count) in counts.iter().enumerate() {
+        println!("{:2} {:>16}", ply, count.separated_string());
+    }
     Ok(())
 }
@@ -0,0 +1,211 @@
+// Depth-first proof-number search (df-pn), a weak solver that only finds whether the player to
+// move wins, draws or loses
+//
+// Proof-number search proves a binary goal, so a position is solved with at most two searches:
+// "the player to move wins" then "the player to move doesn't lose". The proof and disproof
+// numbers use the phi/delta notation of Nagai: at each node phi is the proof number of the goal
+// of the player to move at that node and delta its disproof number.
+
+use crate::position::{Position, HEIGHT, WIDTH};
+use crate::solver::{Outcome, COLUMNS_ORDER};
+
+pub const DEFAULT_TABLE_SIZE: usize = 1 << 20;
+
+const INFINITY: u32 = u32::MAX / 2;
+const PROVEN: (u32, u32) = (0, INFINITY);
+const DISPROVEN: (u32, u32) = (INFINITY, 0);
+
+// keys only use 56 bits
+const EMPTY_KEY: u64 = u64::MAX;
+
+#[derive(Clone, Copy)]
+struct Entry {
+    key: u64,
+    phi: u32,
+    delta: u32,
+}
+
+const EMPTY_ENTRY: Entry = Entry {
+    key: EMPTY_KEY,
+    phi: 0,
+    delta: 0,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Goal {
+    Win,
+    NotLose,
+}
+
+pub struct ProofNumberSolver {
+    /// positions expanded by the search
+    pub nodes: usize,
+    // the table never grows, entries are replaced on collisions
+    table: Vec<Entry>,
+    goal: Goal,
+    root_parity: u64,
+}
+
+impl Default for ProofNumberSolver {
+    fn default() -> Self {
+        Self::new(DEFAULT_TABLE_SIZE)
+    }
+}
+
+impl ProofNumberSolver {
+    pub fn new(table_size: usize) -> ProofNumberSolver {
+        ProofNumberSolver {
+            nodes: 0,
+            table: vec![EMPTY_ENTRY; table_size.max(1)],
+            goal: Goal::Win,
+            root_parity: 0,
+        }
+    }
+
+    pub fn solve(&mut self, p: &Position) -> Outcome {
+        if p.opponent().is_winning() {
+            Outcome::Loss
+        } else if p.can_win_next() {
+            Outcome::Win
+        } else if p.play_count() == WIDTH * HEIGHT {
+            Outcome::Draw
+        } else if self.prove(p, Goal::Win) {
+            Outcome::Win
+        } else if self.prove(p, Goal::NotLose) {
+            Outcome::Draw
+        } else {
+            Outcome::Loss
+        }
+    }
+
+    fn prove(&mut self, p: &Position, goal: Goal) -> bool {
+        self.goal = goal;
+        self.root_parity = p.play_count() % 2;
+        self.table.fill(EMPTY_ENTRY);
+        let (phi, _) = self.mid(p, INFINITY, INFINITY);
+        phi == 0
+    }
+
+    // Multiple iterative deepening: expand `p` until its phi or delta reaches its threshold
+    fn mid(&mut self, p: &Position, phi_threshold: u32, delta_threshold: u32) -> (u32, u32) {
+        self.nodes += 1;
+        if let Some(numbers) = self.terminal(p) {
+            self.store(p.key(), numbers);
+            return numbers;
+        }
+        let plays = p.possible_non_losing_play_mask();
+        let children: Vec<Position> = COLUMNS_ORDER
+            .iter()
+            .filter(|&&c| plays & Position::column_mask(c) != 0)
+            .map(|&c| p.play(c))
+            .collect();
+        // kept locally so that the search progresses even if the table entries are replaced
+        let mut numbers: Vec<(u32, u32)> = children.iter().map(|c| self.lookup(c)).collect();
+        loop {
+            let phi = numbers.iter().map(|&(_, delta)| delta).min().unwrap();
+            let delta = numbers
+                .iter()
+                .fold(0, |sum, &(phi, _)| (sum + phi).min(INFINITY));
+            let (mut best, mut second_delta) = (0, INFINITY);
+            for i in 1..numbers.len() {
+                if numbers[i].1 < numbers[best].1 {
+                    second_delta = numbers[best].1;
+                    best = i;
+                } else if numbers[i].1 < second_delta {
+                    second_delta = numbers[i].1;
+                }
+            }
+            if phi >= phi_threshold || delta >= delta_threshold {
+                self.store(p.key(), (phi, delta));
+                return (phi, delta);
+            }
+            let (best_phi, _) = numbers[best];
+            numbers[best] = self.mid(
+                &children[best],
+                (delta_threshold - delta + best_phi).min(INFINITY),
+                phi_threshold.min(second_delta.saturating_add(1)),
+            );
+        }
+    }
+
+    // Numbers of a position that isn't expanded yet
+    fn lookup(&self, p: &Position) -> (u32, u32) {
+        let entry = &self.table[self.index(p.key())];
+        if entry.key == p.key() {
+            return (entry.phi, entry.delta);
+        }
+        // the more plays, the harder to disprove
+        self.terminal(p)
+            .unwrap_or((1, p.possible_non_losing_play_mask().count_ones()))
+    }
+
+    fn terminal(&self, p: &Position) -> Option<(u32, u32)> {
+        if p.can_win_next() {
+            Some(PROVEN)
+        } else if p.play_count() == WIDTH * HEIGHT {
+            // a draw reaches the goal of the root player only if it is not to lose
+            let root_to_move = p.play_count() % 2 == self.root_parity;
+            if (self.goal == Goal::NotLose) == root_to_move {
+                Some(PROVEN)
+            } else {
+                Some(DISPROVEN)
+            }
+        } else if p.possible_non_losing_play_mask() == 0 {
+            Some(DISPROVEN)
+        } else {
+            None
+        }
+    }
+
+    fn store(&mut self, key: u64, (phi, delta): (u32, u32)) {
+        let index = self.index(key);
+        self.table[index] = Entry { key, phi, delta };
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.table.len() as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::Solver;
+
+    #[test]
+    fn test_solve() {
+        let mut pns = ProofNumberSolver::new(1 << 16);
+        for (moves, score) in [
+            ("2252576253462244111563365343671351441", -1),
+            ("7422341735647741166133573473242566", 1),
+            ("23163416124767223154467471272416755633", 0),
+            ("5554224333234511764415115", 4),
+            ("1233722555341451114725221333", -1),
+        ] {
+            let p: Position = moves.parse().unwrap();
+            assert_eq!(pns.solve(&p), Outcome::from_score(score), "{}", moves);
+        }
+    }
+
+    #[test]
+    fn test_terminal() {
+        let mut pns = ProofNumberSolver::default();
+        // the first player has four in the first column
+        let p: Position = "1212121".parse().unwrap();
+        assert_eq!(pns.solve(&p), Outcome::Loss);
+        assert_eq!(pns.solve(&"121212".parse().unwrap()), Outcome::Win);
+    }
+
+    #[test]
+    fn test_small_table() {
+        // positions of the middle game with a table much smaller than the searched tree
+        let mut solver = Solver::new();
+        let mut pns = ProofNumberSolver::new(64);
+        for moves in ["612575172312315153714", "52753311433677442422121"] {
+            let p: Position = moves.parse().unwrap();
+            let expected = Outcome::from_score(solver.solve(p).value());
+            assert_eq!(pns.solve(&p), expected, "{}", moves);
+            solver.reset();
+        }
+    }
+}
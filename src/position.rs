@@ -1,7 +1,8 @@
 pub const HEIGHT: u64 = 6;
 pub const WIDTH: u64 = 7;
 pub const FULL_HEIGHT: u64 = HEIGHT + 1;
-pub const MIN_SCORE: i32 = -((WIDTH * HEIGHT) as i32) / 2 + 3;
+// Size of the binary encoding of a position, the key has WIDTH * FULL_HEIGHT bits
+pub const KEY_BYTES: usize = (WIDTH * FULL_HEIGHT).div_ceil(8) as usize;
 
 #[derive(Debug, Eq, PartialEq)]
 enum Cell {
@@ -10,6 +11,13 @@ enum Cell {
     OtherPlayer,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Player {
+    First,
+    Second,
+}
+
 /**
  * bit order:
  *
@@ -26,13 +34,13 @@ enum Cell {
  *
  *
  */
-#[derive(Clone)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Position {
     /// stones of the current player
-    pub player: u64,
+    player: u64,
     /// stones of the grid
-    pub mask: u64,
-    pub play_count: u64,
+    mask: u64,
+    play_count: u64,
 }
 
 const FULL_BOTTOM_MASK: u64 = 0b1000000100000010000001000000100000010000001;
@@ -48,73 +56,137 @@ impl Position {
         }
     }
 
-    pub fn play(&self, col_pos: u64) -> Position {
-        let mut p = self.opponent();
-        p.mask = p.mask | p.mask + Self::bottom_mask(col_pos);
-        p.play_count += 1;
-        p
+    // Checked `play`: the column must exist and not be full, and the game must not be over
+    pub fn try_play(&self, col_pos: u64) -> Result<Position, String> {
+        if col_pos >= WIDTH {
+            Err(format!("column {} doesn't exist", col_pos + 1))
+        } else if !self.is_valid_play(col_pos) {
+            Err(format!("column {} is full", col_pos + 1))
+        } else if self.opponent().is_winning() {
+            Err("the game is over".to_string())
+        } else {
+            Ok(self.play(col_pos))
+        }
     }
 
-    pub fn opponent(&self) -> Position {
-        let mut p = self.clone();
-        p.player = self.player ^ self.mask;
+    // The column must not be full, see `try_play`
+    pub(crate) fn play(&self, col_pos: u64) -> Position {
+        let mut p = *self;
+        p.make_move(col_pos);
         p
     }
 
+    // Play in place in the column, undone by `unmake_move` with the same column
+    pub(crate) fn make_move(&mut self, col_pos: u64) {
+        debug_assert!(
+            self.is_valid_play(col_pos),
+            "column {} is full",
+            col_pos + 1
+        );
+        self.make_play(self.possible_mask() & Self::column_mask(col_pos));
+    }
+
+    // Take back the top stone of the column, it must be the last one played
+    pub(crate) fn unmake_move(&mut self, col_pos: u64) {
+        debug_assert!(
+            self.mask & Self::bottom_mask(col_pos) != 0,
+            "column {} is empty",
+            col_pos + 1
+        );
+        // the lowest empty cell of the column shifted down to its top stone
+        let column = self.mask & Self::column_mask(col_pos);
+        self.unmake_play((column + Self::bottom_mask(col_pos)) >> 1);
+    }
+
+    // Same as `make_move` with the cell `play` of `possible_mask`, the search takes the cells
+    // from the non losing plays of the node instead of finding them again from the columns
+    pub(crate) fn make_play(&mut self, play: u64) {
+        debug_assert!(play.count_ones() == 1 && play & self.possible_mask() != 0);
+        self.player ^= self.mask;
+        self.mask |= play;
+        self.play_count += 1;
+    }
+
+    // `play` must be the top stone of its column and belong to the player who just played
+    pub(crate) fn unmake_play(&mut self, play: u64) {
+        debug_assert!(play.count_ones() == 1 && play & self.mask & !self.player != 0);
+        debug_assert!(play & ((self.mask ^ play) + FULL_BOTTOM_MASK) != 0);
+        self.mask ^= play;
+        self.player ^= self.mask;
+        self.play_count -= 1;
+    }
+
+    // The stones of the player to move are swapped, the play count no longer matches the
+    // player to move
+    pub(crate) fn opponent(&self) -> Position {
+        Position {
+            player: self.player ^ self.mask,
+            ..*self
+        }
+    }
+
     pub fn is_valid_play(&self, col_pos: u64) -> bool {
         self.mask & Self::top_mask(col_pos) == 0
     }
 
-    // pub fn is_winning_play(&self, col_pos: u64) -> bool {
-    //     // the current player stones with the play
-    //     // mask + bottom_mask = add a stone in column
-    //     // & column_mask: only modify the current player stone
-    //     // (ignoring other player in other columns)
-    //     let p =
-    //         self.player | ((self.mask + Self::bottom_mask(col_pos)) & Self::column_mask(col_pos));
-    //     let mut p2 = Position::new();
-    //     self.is_winning
-    // }
-    //
+    // The current player wins by playing in this column
+    pub fn is_winning_play(&self, col_pos: u64) -> bool {
+        self.winning_mask() & self.possible_mask() & Self::column_mask(col_pos) != 0
+    }
+
+    // Columns where the current player wins immediately
+    pub fn winning_columns(&self) -> Vec<u64> {
+        Self::columns(self.winning_mask() & self.possible_mask())
+    }
+
+    // Columns where the opponent would win with its next play,
+    // the current player loses if there is more than one
+    pub fn blocking_columns(&self) -> Vec<u64> {
+        Self::columns(self.opponent_winning_mask() & self.possible_mask())
+    }
+
+    fn columns(cells: u64) -> Vec<u64> {
+        (0..WIDTH)
+            .filter(|&c| cells & Self::column_mask(c) != 0)
+            .collect()
+    }
 
     pub fn is_winning(&self) -> bool {
-        let p = self.player;
+        Self::has_alignment(self.player)
+    }
+
+    fn has_alignment(p: u64) -> bool {
         // shift the board in different direction,
         // if the shifed boards have at least 1 stone in common
         // it means that the original has 4 stone aligned.
         // possible optimization with tmp variable to make 2 shifts instead of 4
         // vertical
-        if (p >> 0) & (p >> 1) & (p >> 2) & (p >> 3) != 0 {
+        if p & (p >> 1) & (p >> 2) & (p >> 3) != 0 {
             return true;
         }
         // horizontal
-        if (p >> (0 * FULL_HEIGHT))
-            & (p >> (1 * FULL_HEIGHT))
-            & (p >> (2 * FULL_HEIGHT))
-            & (p >> (3 * FULL_HEIGHT))
-            != 0
-        {
+        if p & (p >> FULL_HEIGHT) & (p >> (2 * FULL_HEIGHT)) & (p >> (3 * FULL_HEIGHT)) != 0 {
             return true;
         }
         // diagonal
-        if (p >> (0 * HEIGHT)) & (p >> (1 * HEIGHT)) & (p >> (2 * HEIGHT)) & (p >> (3 * HEIGHT))
-            != 0
-        {
+        if p & (p >> HEIGHT) & (p >> (2 * HEIGHT)) & (p >> (3 * HEIGHT)) != 0 {
             return true;
         }
         // anti diagonal
-        if (p >> (0 * (HEIGHT + 2)))
-            & (p >> (1 * (HEIGHT + 2)))
-            & (p >> (2 * (HEIGHT + 2)))
-            & (p >> (3 * (HEIGHT + 2)))
-            != 0
-        {
+        if p & (p >> (HEIGHT + 2)) & (p >> (2 * (HEIGHT + 2))) & (p >> (3 * (HEIGHT + 2))) != 0 {
             return true;
         }
         false
     }
 
     pub fn possible_non_losing_play_mask(&self) -> u64 {
+        self.non_losing_plays(self.opponent_winning_mask())
+    }
+
+    // Same as `possible_non_losing_play_mask` with the opponent winning mask already known,
+    // the search gets it from the `played_winning_mask` of the parent node
+    pub(crate) fn non_losing_plays(&self, opponent_win_mask: u64) -> u64 {
+        debug_assert_eq!(opponent_win_mask, self.opponent_winning_mask());
         // + operator from a bitwise perspective is a << and a |
         // (not really but kinda in this case)
         // . . . . . . .
@@ -123,7 +195,6 @@ impl Position {
         // . # # . . . .
         // # . . # # # #
         let mut possible_mask = self.possible_mask();
-        let opponent_win_mask = self.opponent().winning_mask();
         // we HAVE to play where the opponent has a winning play
         let forced_moves = possible_mask & opponent_win_mask;
         if forced_moves != 0 {
@@ -135,7 +206,7 @@ impl Position {
             }
         }
         // Remove moves that are directly bellow an opponent winning space
-        return possible_mask & !(opponent_win_mask >> 1);
+        possible_mask & !(opponent_win_mask >> 1)
     }
 
     pub fn can_win_next(&self) -> bool {
@@ -153,53 +224,128 @@ impl Position {
     // x . x x
     // # x # x
     fn winning_mask(&self) -> u64 {
+        Self::winning_cells(self.player, self.mask)
+    }
+
+    pub(crate) fn opponent_winning_mask(&self) -> u64 {
+        Self::winning_cells(self.player ^ self.mask, self.mask)
+    }
+
+    // Empty cells where `stones` would complete four, playable or not
+    pub fn winning_cells(stones: u64, mask: u64) -> u64 {
         // move player mask 3 times up and & it to keep only the top one
         // vertical
-        let mut r = (self.player << 1) & (self.player << 2) & (self.player << 3);
+        let mut r = (stones << 1) & (stones << 2) & (stones << 3);
 
         //horizontal
         // & horizontal pairs
-        let mut p = (self.player << FULL_HEIGHT) & (self.player << (2 * FULL_HEIGHT));
+        let mut p = (stones << FULL_HEIGHT) & (stones << (2 * FULL_HEIGHT));
         // & with one to the right
-        r |= p & (self.player << (3 * FULL_HEIGHT));
+        r |= p & (stones << (3 * FULL_HEIGHT));
         // & with one to the left
-        r |= p & (self.player >> FULL_HEIGHT);
+        r |= p & (stones >> FULL_HEIGHT);
         p >>= 3 * FULL_HEIGHT; // for the other half of the board since we shifted out part of it?
-        r |= p & (self.player << FULL_HEIGHT);
-        r |= p & (self.player >> (3 * FULL_HEIGHT));
+        r |= p & (stones << FULL_HEIGHT);
+        r |= p & (stones >> (3 * FULL_HEIGHT));
 
         //diagonal 1
-        p = (self.player << HEIGHT) & (self.player << 2 * HEIGHT);
-        r |= p & (self.player << 3 * HEIGHT);
-        r |= p & (self.player >> HEIGHT);
+        p = (stones << HEIGHT) & (stones << (2 * HEIGHT));
+        r |= p & (stones << (3 * HEIGHT));
+        r |= p & (stones >> HEIGHT);
         p >>= 3 * HEIGHT;
-        r |= p & (self.player << HEIGHT);
-        r |= p & (self.player >> 3 * HEIGHT);
+        r |= p & (stones << HEIGHT);
+        r |= p & (stones >> (3 * HEIGHT));
 
         //diagonal 2
-        p = (self.player << (HEIGHT + 2)) & (self.player << 2 * (HEIGHT + 2));
-        r |= p & (self.player << 3 * (HEIGHT + 2));
-        r |= p & (self.player >> (HEIGHT + 2));
+        p = (stones << (HEIGHT + 2)) & (stones << (2 * (HEIGHT + 2)));
+        r |= p & (stones << (3 * (HEIGHT + 2)));
+        r |= p & (stones >> (HEIGHT + 2));
         p >>= 3 * (HEIGHT + 2);
-        r |= p & (self.player << (HEIGHT + 2));
-        r |= p & (self.player >> 3 * (HEIGHT + 2));
+        r |= p & (stones << (HEIGHT + 2));
+        r |= p & (stones >> (3 * (HEIGHT + 2)));
 
-        r & (FULL_BOARD_MASK ^ self.mask) // remove all set bit that are not pieces
+        r & (FULL_BOARD_MASK ^ mask) // remove all set bit that are not pieces
     }
 
     pub fn score(&self) -> u64 {
         self.winning_mask().count_ones() as u64
     }
 
+    // Same as `play(col_pos).opponent().score()` without building the position
+    pub fn play_score(&self, col_pos: u64) -> u64 {
+        self.play_cell_score(self.possible_mask() & Self::column_mask(col_pos))
+    }
+
+    // Same as `play_score` for the cell `play` of `possible_mask`
+    pub(crate) fn play_cell_score(&self, play: u64) -> u64 {
+        self.played_winning_mask(play).count_ones() as u64
+    }
+
+    // Winning cells of the player to move after playing the cell `play`,
+    // the opponent winning mask of the position after the play
+    pub(crate) fn played_winning_mask(&self, play: u64) -> u64 {
+        Self::winning_cells(self.player | play, self.mask | play)
+    }
+
     pub fn is_draw(&self) -> bool {
-        return self.play_count >= WIDTH * HEIGHT - 2; // -2 because we're never actually finishing
-                                                      // (maybe)
+        self.play_count >= WIDTH * HEIGHT - 2 // -2 because we're never actually finishing
+                                              // (maybe)
+    }
+
+    pub fn play_count(&self) -> u64 {
+        self.play_count
+    }
+
+    // Stones of both players
+    pub fn mask(&self) -> u64 {
+        self.mask
     }
 
     pub fn key(&self) -> u64 {
         self.player + self.mask
     }
 
+    // Inverse of `key`
+    // in each column the key is the player stones plus 2^height - 1,
+    // so the highest set bit of column + 1 gives the height of the column.
+    // The position must be reachable in a legal game, like with `from_stones`.
+    pub fn from_key(key: u64) -> Result<Position, String> {
+        if key >> (WIDTH * FULL_HEIGHT) != 0 {
+            return Err(format!("bad position key {:#x}", key));
+        }
+        let mut player = 0;
+        let mut mask = 0;
+        for x in 0..WIDTH {
+            let column = (key & Self::column_mask(x)) >> (x * FULL_HEIGHT);
+            let height = 63 - (column + 1).leading_zeros() as u64;
+            if height > HEIGHT {
+                return Err(format!("bad position key {:#x}", key));
+            }
+            let column_mask = (1 << height) - 1;
+            player |= (column - column_mask) << (x * FULL_HEIGHT);
+            mask |= column_mask << (x * FULL_HEIGHT);
+        }
+        let first = if mask.count_ones().is_multiple_of(2) {
+            player
+        } else {
+            player ^ mask
+        };
+        Position::from_stones(first, first ^ mask)
+    }
+
+    // Fixed size encoding of the key, little endian
+    pub fn to_bytes(&self) -> [u8; KEY_BYTES] {
+        let mut bytes = [0; KEY_BYTES];
+        bytes.copy_from_slice(&self.key().to_le_bytes()[..KEY_BYTES]);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: [u8; KEY_BYTES]) -> Result<Position, String> {
+        let mut key = [0; 8];
+        key[..KEY_BYTES].copy_from_slice(&bytes);
+        Position::from_key(u64::from_le_bytes(key))
+    }
+
     fn bottom_mask(col_pos: u64) -> u64 {
         1 << (col_pos * FULL_HEIGHT)
     }
@@ -212,6 +358,222 @@ impl Position {
         0b1111111 << (col_pos * FULL_HEIGHT)
     }
 
+    pub fn first_player_stones(&self) -> u64 {
+        if self.play_count.is_multiple_of(2) {
+            self.player
+        } else {
+            self.player ^ self.mask
+        }
+    }
+
+    pub fn second_player_stones(&self) -> u64 {
+        self.first_player_stones() ^ self.mask
+    }
+
+    // Rows from top to bottom separated by '/', '1' and '2' are the stones
+    // of the first and second player, '.' is an empty cell
+    pub fn to_board_string(&self) -> String {
+        let first = self.first_player_stones();
+        (0..HEIGHT)
+            .rev()
+            .map(|y| {
+                (0..WIDTH)
+                    .map(|x| {
+                        let cell = 1 << (x * FULL_HEIGHT + y);
+                        if self.mask & cell == 0 {
+                            '.'
+                        } else if first & cell != 0 {
+                            '1'
+                        } else {
+                            '2'
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    pub fn from_board_string(s: &str) -> Result<Position, String> {
+        let rows: Vec<&str> = s.split('/').collect();
+        if rows.len() != HEIGHT as usize {
+            return Err(format!("bad board string \"{}\"", s));
+        }
+        let mut first = 0;
+        let mut second = 0;
+        for (row, y) in rows.iter().zip((0..HEIGHT).rev()) {
+            if row.chars().count() != WIDTH as usize {
+                return Err(format!("bad board string \"{}\"", s));
+            }
+            for (c, x) in row.chars().zip(0..WIDTH) {
+                let cell = 1 << (x * FULL_HEIGHT + y);
+                match c {
+                    '1' => first |= cell,
+                    '2' => second |= cell,
+                    '.' => (),
+                    _ => return Err(format!("bad board string \"{}\"", s)),
+                }
+            }
+        }
+        Position::from_stones(first, second)
+    }
+
+    // Position from the stones of each player, the first player moves if both have the
+    // same number of stones. The position must be reachable in a legal game:
+    // stones stacked from the bottom, alternating turns and no play after a win
+    pub fn from_stones(first: u64, second: u64) -> Result<Position, String> {
+        let p = Position::from_stones_unreachable(first, second)?;
+        p.validate_reachable()?;
+        Ok(p)
+    }
+
+    // Cheap checks of `from_stones`, the position may still not be reachable
+    fn from_stones_unreachable(first: u64, second: u64) -> Result<Position, String> {
+        if (first | second) & !FULL_BOARD_MASK != 0 {
+            return Err("stones outside of the board".to_string());
+        }
+        if first & second != 0 {
+            return Err("both players have a stone on the same cell".to_string());
+        }
+        let mask = first | second;
+        for x in 0..WIDTH {
+            // stones must be stacked from the bottom of the column
+            let column = (mask & Self::column_mask(x)) >> (x * FULL_HEIGHT);
+            if column & (column + 1) != 0 {
+                return Err(format!("floating stone in column {}", x + 1));
+            }
+        }
+        let (first_count, second_count) = (first.count_ones(), second.count_ones());
+        if first_count != second_count && first_count != second_count + 1 {
+            return Err(format!(
+                "bad stone count, {} for the first player and {} for the second",
+                first_count, second_count
+            ));
+        }
+        let play_count = (first_count + second_count) as u64;
+        let p = Position {
+            player: if play_count.is_multiple_of(2) {
+                first
+            } else {
+                second
+            },
+            mask,
+            play_count,
+        };
+        // the game would have ended before the last move
+        if p.is_winning() {
+            return Err("the player to move already won".to_string());
+        }
+        Ok(p)
+    }
+
+    // Expensive search of a legal game leading to the position, for untrusted positions
+    pub fn validate_reachable(&self) -> Result<(), String> {
+        if self.is_reachable(&mut HashSet::new()) {
+            Ok(())
+        } else {
+            Err("the position can't be reached in a legal game".to_string())
+        }
+    }
+
+    // Look for a sequence of moves leading to this position where nobody wins before the
+    // last move, by undoing the last move in every possible column.
+    // A sub position is identified by its mask, `dead_ends` holds the ones already explored.
+    fn is_reachable(&self, dead_ends: &mut HashSet<u64>) -> bool {
+        if self.play_count == 0 {
+            return true;
+        }
+        // stones of the player who made the last move
+        let last = self.player ^ self.mask;
+        for x in 0..WIDTH {
+            // top stone of the column
+            let top = (self.mask + Self::bottom_mask(x)) & Self::column_mask(x) & !self.mask;
+            let stone = top >> 1;
+            if stone & last == 0 {
+                continue;
+            }
+            let previous = Position {
+                player: last ^ stone,
+                mask: self.mask ^ stone,
+                play_count: self.play_count - 1,
+            };
+            if Self::has_alignment(previous.player)
+                || Self::has_alignment(previous.player ^ previous.mask)
+                || dead_ends.contains(&previous.mask)
+            {
+                continue;
+            }
+            if previous.is_reachable(dead_ends) {
+                return true;
+            }
+            dead_ends.insert(previous.mask);
+        }
+        false
+    }
+
+    // Position with the columns in reverse order
+    pub fn mirror(&self) -> Position {
+        Position {
+            player: Self::mirror_bits(self.player),
+            mask: Self::mirror_bits(self.mask),
+            play_count: self.play_count,
+        }
+    }
+
+    fn mirror_bits(bits: u64) -> u64 {
+        (0..WIDTH).fold(0, |acc, x| {
+            let column = (bits & Self::column_mask(x)) >> (x * FULL_HEIGHT);
+            acc | column << ((WIDTH - 1 - x) * FULL_HEIGHT)
+        })
+    }
+
+    // Both halves of the board are the same,
+    // playing in a column or in its mirror leads to equivalent positions
+    pub fn is_symmetric(&self) -> bool {
+        self.mask == Self::mirror_bits(self.mask) && self.player == Self::mirror_bits(self.player)
+    }
+
+    // Columns right of the center, mirrors of the columns left of it
+    pub fn right_half_mask() -> u64 {
+        (WIDTH / 2 + 1..WIDTH).fold(0, |mask, c| mask | Self::column_mask(c))
+    }
+
+    // Same key for a position and its mirror
+    pub fn symmetric_key(&self) -> u64 {
+        self.key().min(Self::mirror_bits(self.key()))
+    }
+
+    // The last play won or the board is full
+    pub fn is_game_over(&self) -> bool {
+        self.opponent().is_winning() || self.play_count == WIDTH * HEIGHT
+    }
+
+    // Number of move sequences of `depth` plies from this position,
+    // the games that end before are not counted
+    pub fn perft(&self, depth: u64) -> u64 {
+        let mut p = *self;
+        p.perft_in_place(depth)
+    }
+
+    // `self` is played on in place and restored before returning
+    fn perft_in_place(&mut self, depth: u64) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        if self.is_game_over() {
+            return 0;
+        }
+        let mut count = 0;
+        for c in 0..WIDTH {
+            if self.is_valid_play(c) {
+                self.make_move(c);
+                count += self.perft_in_place(depth - 1);
+                self.unmake_move(c);
+            }
+        }
+        count
+    }
+
     fn at(&self, y: u64, x: u64) -> Cell {
         let pos_mask = (1 << (x * FULL_HEIGHT)) << y;
         if self.mask & pos_mask == 0 {
@@ -220,35 +582,125 @@ impl Position {
         if self.player & pos_mask != 0 {
             return Cell::CurrentPlayer;
         }
-        return Cell::OtherPlayer;
+        Cell::OtherPlayer
     }
 }
 
-impl From<&[u64]> for Position {
-    fn from(plays: &[u64]) -> Self {
-        let mut position = Position::new();
-        for col_pos in plays {
-            position = position.play(*col_pos);
-        }
-        position
+impl Default for Position {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TryFrom<&[u64]> for Position {
+    type Error = String;
+    fn try_from(plays: &[u64]) -> Result<Self, Self::Error> {
+        plays
+            .iter()
+            .try_fold(Position::new(), |position, &col_pos| {
+                position.try_play(col_pos)
+            })
     }
 }
 
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::str::FromStr;
 
 impl FromStr for Position {
     type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let it = s.chars().map(|c| c.to_digit(10).unwrap() as u64 - 1);
-        if it.clone().any(|x| x >= WIDTH) {
-            return Err(format!("bad position string format \"{}\"", s));
+        let plays = parse_moves(s)?;
+        Position::try_from(&plays[..]).map_err(|e| format!("{} in \"{}\"", e, s))
+    }
+}
+
+// Number of distinct positions after each ply up to `depth` starting from the empty board,
+// a position and its mirror are counted once if `symmetric` is set
+pub fn count_positions(depth: u64, symmetric: bool) -> Vec<u64> {
+    let mut counts = vec![1];
+    let mut positions = vec![Position::new()];
+    for _ in 0..depth {
+        let mut seen = HashSet::new();
+        let mut next = Vec::new();
+        for p in positions.iter().filter(|p| !p.is_game_over()) {
+            for c in (0..WIDTH).filter(|&c| p.is_valid_play(c)) {
+                let child = p.play(c);
+                let key = if symmetric {
+                    child.symmetric_key()
+                } else {
+                    child.key()
+                };
+                if seen.insert(key) {
+                    next.push(child);
+                }
+            }
         }
-        Ok(Position::from(&it.collect::<Vec<u64>>()[..]))
+        counts.push(next.len() as u64);
+        positions = next;
+    }
+    counts
+}
+
+// Columns of a move string, doesn't check that the moves are valid
+pub fn parse_moves(s: &str) -> Result<Vec<u64>, String> {
+    s.chars()
+        .map(|c| match c.to_digit(10) {
+            Some(d) if d >= 1 && d as u64 <= WIDTH => Ok(d as u64 - 1),
+            _ => Err(format!("bad position string format \"{}\"", s)),
+        })
+        .collect()
+}
+
+// Inverse of `parse_moves`, columns are 1 indexed
+pub fn moves_to_string(moves: &[u64]) -> String {
+    moves
+        .iter()
+        .map(|&c| char::from_digit(c as u32 + 1, 10).unwrap())
+        .collect()
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Position {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_board_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Position {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Position::from_board_string(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+// Serialize a list of moves as a move string with `#[serde(with = "crate::position::moves_serde")]`
+#[cfg(feature = "serde")]
+pub mod moves_serde {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(moves: &[u64], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::moves_to_string(moves))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u64>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        super::parse_moves(&s).map_err(serde::de::Error::custom)
     }
 }
 
 use std::fmt;
 
+impl fmt::Display for Player {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Player::First => "first player",
+            Player::Second => "second player",
+        })
+    }
+}
+
 impl fmt::Debug for Position {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use Cell::*;
@@ -276,7 +728,7 @@ impl fmt::Debug for Position {
                     CurrentPlayer => write!(f, "# ")?,
                 }
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
@@ -405,6 +857,22 @@ mod tests {
         assert!(p.is_winning_play(3), "\n{:?}", p); // anti diagonal
     }
 
+    #[test]
+    fn test_winning_columns() {
+        let p = Position::from_str("121212").unwrap();
+        assert_eq!(p.winning_columns(), vec![0]);
+        assert_eq!(p.blocking_columns(), vec![1]);
+        let p = p.play(3);
+        assert_eq!(p.winning_columns(), vec![1]);
+        assert_eq!(p.blocking_columns(), vec![0]);
+
+        // horizontal threats on both sides
+        let p = Position::from_str("334455").unwrap();
+        assert_eq!(p.winning_columns(), vec![1, 5]);
+        assert_eq!(p.opponent().blocking_columns(), vec![1, 5]);
+        assert!(Position::new().winning_columns().is_empty());
+    }
+
     #[test]
     fn test_is_draw() {}
 
@@ -440,15 +908,22 @@ mod tests {
 
     #[test]
     fn test_from_slice() {
-        let p = Position::from(&[0, 1, 2][..]);
+        let p = Position::try_from(&[0, 1, 2][..]).unwrap();
         assert_eq!(p.at(0, 0), Cell::OtherPlayer, "\n{:?}", p);
         assert_eq!(p.at(0, 1), Cell::CurrentPlayer, "\n{:?}", p);
         assert_eq!(p.at(0, 2), Cell::OtherPlayer, "\n{:?}", p);
 
-        let p = Position::from(&[0, 0, 0][..]);
+        let p = Position::try_from(&[0, 0, 0][..]).unwrap();
         assert_eq!(p.at(0, 0), Cell::OtherPlayer, "\n{:?}", p);
         assert_eq!(p.at(1, 0), Cell::CurrentPlayer, "\n{:?}", p);
         assert_eq!(p.at(2, 0), Cell::OtherPlayer, "\n{:?}", p);
+
+        assert!(Position::try_from(&[0; 7][..]).is_err());
+        assert!(Position::try_from(&[WIDTH][..]).is_err());
+        // the first player won with its last move
+        assert!(Position::try_from(&[0, 1, 0, 1, 0, 1, 0][..]).is_ok());
+        assert!(Position::try_from(&[0, 1, 0, 1, 0, 1, 0, 1][..]).is_err());
+        assert!(Position::from_str("12121212").is_err());
     }
 
     #[test]
@@ -463,8 +938,177 @@ mod tests {
         assert_eq!(p.at(1, 0), Cell::CurrentPlayer, "\n{:?}", p);
         assert_eq!(p.at(2, 0), Cell::OtherPlayer, "\n{:?}", p);
 
-        // assert!(std::panic::catch_unwind(|| Position::from_str("a")).is_err());
-        // assert!(std::panic::catch_unwind(|| Position::from_str("7")).is_err());
-        // assert!(std::panic::catch_unwind(|| Position::from_str("00 0")).is_err());
+        assert!(Position::from_str("a").is_err());
+        assert!(Position::from_str("8").is_err());
+        assert!(Position::from_str("00 0").is_err());
+        assert!(Position::from_str("1111111").is_err());
+    }
+
+    #[test]
+    fn test_board_string() {
+        let p = Position::from_str("4453").unwrap();
+        let board = "......./......./......./......./...2.../..211..";
+        assert_eq!(p.to_board_string(), board);
+        assert!(Position::from_board_string(board).unwrap() == p);
+        assert_eq!(
+            Position::from_board_string(&Position::new().to_board_string())
+                .unwrap()
+                .play_count,
+            0
+        );
+        let p = Position::from_str("445").unwrap();
+        assert!(Position::from_board_string(&p.to_board_string()).unwrap() == p);
+
+        // floating stone
+        assert!(
+            Position::from_board_string("......./......./......./......1/...2.../..211..").is_err()
+        );
+        // too many stones for the first player
+        assert!(
+            Position::from_board_string("......./......./......./......./......./...111.").is_err()
+        );
+        // the first player won with the last move
+        assert!(
+            Position::from_board_string("......./......./......./......./222..../1111...").is_ok()
+        );
+        // the second player played after the end of the game
+        assert!(
+            Position::from_board_string("......./......./......./......./2222.../1111...").is_err()
+        );
+        // the last stone of the second player is under a stone of the first player
+        assert!(
+            Position::from_board_string("......./......./......./......./1....../2......").is_err()
+        );
+        assert!(Position::from_board_string("......./.......").is_err());
+        assert!(
+            Position::from_board_string("......./......./......./......./......./...x...").is_err()
+        );
+    }
+
+    #[test]
+    fn test_from_stones() {
+        let p = Position::from_str("4453").unwrap();
+        let (first, second) = (p.first_player_stones(), p.second_player_stones());
+        assert!(Position::from_stones(first, second).unwrap() == p);
+        assert!(Position::from_stones(first, second | first).is_err());
+        // stone in the sentinel row
+        assert!(Position::from_stones(first | 1 << HEIGHT, second).is_err());
+        assert!(Position::from_stones(first, 0).is_err());
+
+        // both players have four, the first player won before the last move
+        let p = Position::from_str("1212121").unwrap().play(1);
+        let (first, second) = (p.first_player_stones(), p.second_player_stones());
+        assert!(Position::from_stones(first, second).is_err());
+        // the first player has two fours, one of them was completed before the last move
+        let first = 0b1111 | 0b1111 << FULL_HEIGHT;
+        let second =
+            0b111 << (2 * FULL_HEIGHT) | 0b111 << (3 * FULL_HEIGHT) | 1 << (4 * FULL_HEIGHT);
+        let unreachable = Position::from_stones_unreachable(first, second).unwrap();
+        assert!(Position::from_stones(first, second).is_err());
+        assert!(Position::from_key(unreachable.key()).is_err());
+    }
+
+    #[test]
+    fn test_mirror() {
+        let p = Position::from_str("4453").unwrap();
+        let mirror = Position::from_str("4435").unwrap();
+        assert!(p.mirror() == mirror);
+        assert!(p.mirror().mirror() == p);
+        assert_eq!(p.symmetric_key(), mirror.symmetric_key());
+        assert!(!p.is_symmetric());
+        assert!(Position::new().is_symmetric());
+        assert!(Position::from_str("441177").unwrap().is_symmetric());
+        assert!(!Position::from_str("4471").unwrap().is_symmetric());
+    }
+
+    #[test]
+    fn test_make_unmake_play() {
+        let moves = parse_moves("612575172312315153714224").unwrap();
+        let mut p = Position::new();
+        let mut history = vec![p];
+        let mut plays = Vec::new();
+        for &c in &moves {
+            let play = p.possible_mask() & Position::column_mask(c);
+            let score = p.play_score(c);
+            assert_eq!(p.play_cell_score(play), score);
+            let opponent_win_mask = p.played_winning_mask(play);
+            p.make_play(play);
+            assert!(p == history.last().unwrap().play(c));
+            assert_eq!(p.opponent().score(), score);
+            assert_eq!(p.opponent_winning_mask(), opponent_win_mask);
+            history.push(p);
+            plays.push(play);
+        }
+        for &play in plays.iter().rev() {
+            history.pop();
+            p.unmake_play(play);
+            assert!(p == *history.last().unwrap());
+        }
+
+        for &c in &moves {
+            p.make_move(c);
+        }
+        assert!(p == Position::try_from(&moves[..]).unwrap());
+        for &c in moves.iter().rev() {
+            p.unmake_move(c);
+        }
+        assert!(p == Position::new());
+    }
+
+    #[test]
+    fn test_perft() {
+        let p = Position::new();
+        assert_eq!(p.perft(0), 1);
+        assert_eq!(p.perft(1), 7);
+        assert_eq!(p.perft(4), 2401);
+        // a column can only hold 6 stones
+        assert_eq!(p.perft(7), 823_536);
+        // first player already won
+        assert_eq!(Position::from_str("1212121").unwrap().perft(1), 0);
+    }
+
+    #[test]
+    fn test_count_positions() {
+        // https://oeis.org/A212693
+        assert_eq!(
+            count_positions(8, false),
+            [1, 7, 49, 238, 1120, 4263, 16422, 54859, 184275]
+        );
+        assert_eq!(&count_positions(2, true), &[1, 4, 25]);
+    }
+
+    #[test]
+    fn test_from_key() {
+        let moves = "612575172312315153714224";
+        for i in 0..=moves.len() {
+            let p = Position::from_str(&moves[..i]).unwrap();
+            assert!(Position::from_key(p.key()).unwrap() == p, "\n{:?}", p);
+            assert!(
+                Position::from_bytes(p.to_bytes()).unwrap() == p,
+                "\n{:?}",
+                p
+            );
+        }
+        // both players won
+        let p = Position::from_str("1212121").unwrap().play(1);
+        assert!(Position::from_key(p.key()).is_err());
+
+        assert_eq!(Position::new().to_bytes(), [0; KEY_BYTES]);
+        // higher than the board
+        assert!(Position::from_key(0b1111111).is_err());
+        assert!(Position::from_key(1 << (WIDTH * FULL_HEIGHT)).is_err());
+        // a single stone for the player to move
+        assert!(Position::from_key(0b11).is_err());
+        assert!(Position::from_bytes([0xff; KEY_BYTES]).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let p = Position::from_str("4453").unwrap();
+        let json = serde_json::to_string(&p).unwrap();
+        assert_eq!(json, format!("\"{}\"", p.to_board_string()));
+        assert!(serde_json::from_str::<Position>(&json).unwrap() == p);
+        assert!(serde_json::from_str::<Position>("\"1\"").is_err());
     }
 }
@@ -0,0 +1,151 @@
+use std::fmt;
+
+use crate::position::{moves_to_string, Position, HEIGHT, WIDTH};
+use crate::random::{Rng, DEFAULT_MAX_ATTEMPTS};
+use crate::solver::Solver;
+
+// A position where the player to move has exactly one column leading to a forced win
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Puzzle {
+    /// moves leading to the puzzle position
+    #[cfg_attr(feature = "serde", serde(with = "crate::position::moves_serde"))]
+    pub moves: Vec<u64>,
+    /// number of moves the player to move needs to win
+    pub win_in: u64,
+    /// best plays of both players, starting with the unique winning move
+    #[cfg_attr(feature = "serde", serde(with = "crate::position::moves_serde"))]
+    pub solution: Vec<u64>,
+}
+
+// Formatted as the data files: `<moves> <win_in> <solution>`
+impl fmt::Display for Puzzle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            moves_to_string(&self.moves),
+            self.win_in,
+            moves_to_string(&self.solution)
+        )
+    }
+}
+
+pub struct PuzzleGenerator {
+    pub max_win_in: u64,
+    /// range of the random game length before the puzzle position
+    pub min_plies: u64,
+    pub max_plies: u64,
+    /// random games tried by `generate`
+    pub max_attempts: u64,
+    rng: Rng,
+}
+
+impl PuzzleGenerator {
+    pub fn new(max_win_in: u64, seed: u64) -> PuzzleGenerator {
+        PuzzleGenerator {
+            max_win_in,
+            min_plies: 16,
+            max_plies: WIDTH * HEIGHT - 2,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            rng: Rng::new(seed),
+        }
+    }
+
+    pub fn generate(&mut self, solver: &mut Solver) -> Result<Puzzle, String> {
+        if self.max_win_in == 0 {
+            return Err("a puzzle needs at least one move to win".to_string());
+        }
+        if self.min_plies > self.max_plies || self.max_plies >= WIDTH * HEIGHT {
+            return Err(format!(
+                "no puzzle after {} to {} plies",
+                self.min_plies, self.max_plies
+            ));
+        }
+        for _ in 0..self.max_attempts {
+            if let Some(puzzle) = self.try_generate(solver) {
+                return Ok(puzzle);
+            }
+        }
+        Err(format!(
+            "no puzzle with a win in at most {} found in {} attempts",
+            self.max_win_in, self.max_attempts
+        ))
+    }
+
+    // Play a random game and check if the final position is a valid puzzle
+    fn try_generate(&mut self, solver: &mut Solver) -> Option<Puzzle> {
+        let plies = self.min_plies + self.rng.below(self.max_plies - self.min_plies + 1);
+        let mut p = Position::new();
+        let mut moves = Vec::new();
+        while p.play_count() < plies {
+            let valid: Vec<u64> = (0..WIDTH).filter(|&c| p.is_valid_play(c)).collect();
+            let c = valid[self.rng.below(valid.len() as u64) as usize];
+            moves.push(c);
+            p = p.play(c);
+            if p.opponent().is_winning() {
+                return None; // the game is over
+            }
+        }
+
+        let scores = solver.analyze(&p);
+        let mut winning = scores.iter().filter_map(|&s| s.filter(|s| s.is_win()));
+        let score = winning.next()?;
+        if winning.next().is_some() {
+            return None; // several solutions
+        }
+        let win_in = score.moves_to_end();
+        if win_in > self.max_win_in {
+            return None;
+        }
+        Some(Puzzle {
+            moves,
+            win_in,
+            solution: solver.principal_variation(p),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_generate() {
+        let mut solver = Solver::new();
+        let mut generator = PuzzleGenerator::new(3, 42);
+        generator.min_plies = 32;
+        for _ in 0..3 {
+            let puzzle = generator.generate(&mut solver).unwrap();
+            assert!(puzzle.win_in <= 3);
+            assert_eq!(puzzle.solution.len() as u64, 2 * puzzle.win_in - 1);
+
+            let mut p = Position::try_from(&puzzle.moves[..]).unwrap();
+            let scores = solver.analyze(&p);
+            let winning: Vec<u64> = (0..WIDTH)
+                .filter(|&c| scores[c as usize].is_some_and(|s| s.is_win()))
+                .collect();
+            assert_eq!(winning, vec![puzzle.solution[0]], "{}", puzzle);
+
+            for &c in &puzzle.solution {
+                assert!(!p.opponent().is_winning(), "{}", puzzle);
+                p = p.play(c);
+            }
+            assert!(p.opponent().is_winning(), "{}", puzzle);
+        }
+    }
+    #[test]
+    fn test_impossible() {
+        let mut solver = Solver::new();
+        assert!(PuzzleGenerator::new(0, 42).generate(&mut solver).is_err());
+
+        let mut generator = PuzzleGenerator::new(3, 42);
+        generator.min_plies = 20;
+        generator.max_plies = 10;
+        assert!(generator.generate(&mut solver).is_err());
+
+        generator.max_plies = 30;
+        generator.max_attempts = 0;
+        assert!(generator.generate(&mut solver).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+// Seedable random positions for tests and data generation
+
+use std::convert::TryFrom;
+use std::ops::RangeInclusive;
+
+use crate::position::{Position, HEIGHT, WIDTH};
+use crate::score::Score;
+use crate::solver::Solver;
+
+pub const DEFAULT_MAX_ATTEMPTS: u64 = 10_000;
+
+pub struct RandomPositionGenerator {
+    /// number of moves played from the empty board
+    pub plies: u64,
+    /// the last move may win or fill the board
+    pub allow_game_over: bool,
+    /// the player to move can't win with the next move
+    pub no_immediate_win: bool,
+    /// extra weight of a column for each step towards the center, 0 for a uniform choice
+    pub center_bias: u64,
+    /// random games tried by `generate`, positions solved by `generate_with_score`
+    pub max_attempts: u64,
+    rng: Rng,
+}
+
+impl RandomPositionGenerator {
+    pub fn new(plies: u64, seed: u64) -> RandomPositionGenerator {
+        RandomPositionGenerator {
+            plies,
+            allow_game_over: false,
+            no_immediate_win: false,
+            center_bias: 0,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            rng: Rng::new(seed),
+        }
+    }
+
+    // Moves leading to a random position satisfying the constraints
+    pub fn generate(&mut self) -> Result<Vec<u64>, String> {
+        self.check_plies()?;
+        for _ in 0..self.max_attempts {
+            if let Some(moves) = self.try_generate() {
+                return Ok(moves);
+            }
+        }
+        Err(format!(
+            "no position found in {} attempts",
+            self.max_attempts
+        ))
+    }
+
+    // Same as `generate` with a solver score of the player to move in `scores`
+    pub fn generate_with_score(
+        &mut self,
+        solver: &mut Solver,
+        scores: RangeInclusive<i32>,
+    ) -> Result<(Vec<u64>, Score), String> {
+        if self.allow_game_over {
+            return Err("a finished game has no score".to_string());
+        }
+        self.check_plies()?;
+        let lowest = Score::loss_next(self.plies).value();
+        let mut highest = Score::win_now(self.plies).value();
+        if self.no_immediate_win {
+            highest -= 1;
+        }
+        if scores.is_empty() || *scores.end() < lowest || *scores.start() > highest {
+            return Err(format!(
+                "the scores after {} plies are between {} and {}",
+                self.plies, lowest, highest
+            ));
+        }
+        for _ in 0..self.max_attempts {
+            let moves = self.generate()?;
+            let score = solver.solve(Position::try_from(&moves[..]).unwrap());
+            if scores.contains(&score.value()) {
+                return Ok((moves, score));
+            }
+        }
+        Err(format!(
+            "no position with a score in {:?} found in {} attempts",
+            scores, self.max_attempts
+        ))
+    }
+
+    fn check_plies(&self) -> Result<(), String> {
+        if self.plies > WIDTH * HEIGHT || (self.plies == WIDTH * HEIGHT && !self.allow_game_over) {
+            return Err(format!("no position after {} plies", self.plies));
+        }
+        Ok(())
+    }
+
+    fn try_generate(&mut self) -> Option<Vec<u64>> {
+        let mut p = Position::new();
+        let mut moves = Vec::new();
+        while p.play_count() < self.plies {
+            if p.is_game_over() {
+                return None;
+            }
+            let c = self.random_column(&p);
+            moves.push(c);
+            p = p.play(c);
+        }
+        if !self.allow_game_over && p.is_game_over() {
+            return None;
+        }
+        if self.no_immediate_win && p.can_win_next() {
+            return None;
+        }
+        Some(moves)
+    }
+
+    fn random_column(&mut self, p: &Position) -> u64 {
+        let center = WIDTH / 2;
+        let weights: Vec<(u64, u64)> = (0..WIDTH)
+            .filter(|&c| p.is_valid_play(c))
+            .map(|c| {
+                (
+                    c,
+                    1 + self.center_bias * (center - (c as i64 - center as i64).unsigned_abs()),
+                )
+            })
+            .collect();
+        let mut r = self.rng.below(weights.iter().map(|&(_, w)| w).sum());
+        for &(c, w) in &weights {
+            if r < w {
+                return c;
+            }
+            r -= w;
+        }
+        unreachable!()
+    }
+}
+
+// xorshift64*, we don't need anything fancy to pick random moves
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        // one splitmix64 step, a bijection so that different seeds give different states
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        // the state must never be 0
+        Rng(if z == 0 { 0x9e37_79b9_7f4a_7c15 } else { z })
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let mut generator = RandomPositionGenerator::new(20, 1);
+        generator.no_immediate_win = true;
+        for _ in 0..20 {
+            let moves = generator.generate().unwrap();
+            assert_eq!(moves.len(), 20);
+            let p = Position::try_from(&moves[..]).unwrap();
+            assert!(!p.is_game_over());
+            assert!(!p.can_win_next());
+        }
+        // same seed, same positions
+        assert_eq!(
+            RandomPositionGenerator::new(10, 7).generate(),
+            RandomPositionGenerator::new(10, 7).generate()
+        );
+        // consecutive seeds aren't merged
+        assert_ne!(
+            RandomPositionGenerator::new(12, 0).generate(),
+            RandomPositionGenerator::new(12, 1).generate()
+        );
+
+        assert!(RandomPositionGenerator::new(WIDTH * HEIGHT, 1)
+            .generate()
+            .is_err());
+        // a full board without a winner is very unlikely
+        let mut generator = RandomPositionGenerator::new(WIDTH * HEIGHT, 1);
+        generator.allow_game_over = true;
+        generator.max_attempts = 1;
+        assert!(generator.generate().is_err());
+    }
+
+    #[test]
+    fn test_center_bias() {
+        let mut generator = RandomPositionGenerator::new(1, 3);
+        generator.center_bias = 10;
+        let center = (0..100)
+            .filter(|_| generator.generate().unwrap() == [3])
+            .count();
+        // the center column has a weight of 31 out of 97
+        assert!(center > 10, "{}", center);
+    }
+
+    #[test]
+    fn test_generate_with_score() {
+        let mut solver = Solver::new();
+        let mut generator = RandomPositionGenerator::new(34, 5);
+        for _ in 0..3 {
+            let (moves, score) = generator.generate_with_score(&mut solver, 1..=4).unwrap();
+            assert!((1..=4).contains(&score.value()));
+            assert_eq!(solver.solve(Position::try_from(&moves[..]).unwrap()), score);
+        }
+
+        // impossible scores are rejected before any search
+        solver.reset();
+        assert!(generator
+            .generate_with_score(&mut solver, RangeInclusive::new(3, 2))
+            .is_err());
+        assert!(generator.generate_with_score(&mut solver, 5..=10).is_err());
+        assert!(generator
+            .generate_with_score(&mut solver, -20..=-5)
+            .is_err());
+        generator.no_immediate_win = true;
+        assert!(generator.generate_with_score(&mut solver, 4..=4).is_err());
+        assert_eq!(solver.visited, 0);
+    }
+}
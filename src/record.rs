@@ -0,0 +1,459 @@
+// PGN like game records
+//
+// [First "alice"]
+// [Second "connect4"]
+// [Date "2026.10.18"]
+// [Result "1-0"]
+// [Size "7x6"]
+// [TimeControl "300+5"]
+//
+// 1. 4 {[%eval 1] the best opening} 4 2. 5 3 ... 1-0
+//
+// Columns are 1 indexed as in the move strings. A comment follows the move it annotates and
+// can start with the score of the move from the point of view of the player who made it.
+// In comments `\}` and `\\` stand for `}` and `\`, and `\[` for a `[` starting the text.
+// A file can contain several games, each one ends with its result.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::position::{Position, HEIGHT, WIDTH};
+use crate::score::Score;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GameResult {
+    FirstWins,
+    SecondWins,
+    Draw,
+    Unknown,
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            GameResult::FirstWins => "1-0",
+            GameResult::SecondWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*",
+        })
+    }
+}
+
+impl FromStr for GameResult {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1-0" => Ok(GameResult::FirstWins),
+            "0-1" => Ok(GameResult::SecondWins),
+            "1/2-1/2" => Ok(GameResult::Draw),
+            "*" => Ok(GameResult::Unknown),
+            _ => Err(format!("bad result \"{}\"", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Move {
+    pub column: u64,
+    pub eval: Option<Score>,
+    pub comment: Option<String>,
+}
+
+impl Move {
+    pub fn new(column: u64) -> Move {
+        Move {
+            column,
+            eval: None,
+            comment: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameRecord {
+    pub first: String,
+    pub second: String,
+    /// YYYY.MM.DD, unknown parts are replaced by ?
+    pub date: String,
+    pub result: GameResult,
+    pub width: u64,
+    pub height: u64,
+    pub time_control: Option<String>,
+    /// tags without a dedicated field, in file order
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<Move>,
+}
+
+impl GameRecord {
+    pub fn new() -> GameRecord {
+        GameRecord {
+            first: "?".to_string(),
+            second: "?".to_string(),
+            date: "????.??.??".to_string(),
+            result: GameResult::Unknown,
+            width: WIDTH,
+            height: HEIGHT,
+            time_control: None,
+            tags: Vec::new(),
+            moves: Vec::new(),
+        }
+    }
+
+    pub fn columns(&self) -> Vec<u64> {
+        self.moves.iter().map(|m| m.column).collect()
+    }
+
+    // Replay the game, fails if a move is illegal or the board isn't the one of the solver
+    pub fn position(&self) -> Result<Position, String> {
+        if (self.width, self.height) != (WIDTH, HEIGHT) {
+            return Err(format!(
+                "unsupported board size {}x{}",
+                self.width, self.height
+            ));
+        }
+        let mut p = Position::new();
+        for (i, m) in self.moves.iter().enumerate() {
+            p = p
+                .try_play(m.column)
+                .map_err(|e| format!("{} at ply {}", e, i + 1))?;
+        }
+        Ok(p)
+    }
+
+    fn set_tag(&mut self, name: String, value: String) -> Result<(), String> {
+        match name.as_str() {
+            "First" => self.first = value,
+            "Second" => self.second = value,
+            "Date" => self.date = value,
+            "Result" => self.result = value.parse()?,
+            "Size" => {
+                let size = value
+                    .split_once('x')
+                    .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+                match size {
+                    Some((width, height)) => {
+                        self.width = width;
+                        self.height = height;
+                    }
+                    None => return Err(format!("bad board size \"{}\"", value)),
+                }
+            }
+            "TimeControl" => self.time_control = Some(value),
+            _ => self.tags.push((name, value)),
+        }
+        Ok(())
+    }
+}
+
+impl Default for GameRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut tags = vec![
+            ("First", self.first.clone()),
+            ("Second", self.second.clone()),
+            ("Date", self.date.clone()),
+            ("Result", self.result.to_string()),
+            ("Size", format!("{}x{}", self.width, self.height)),
+        ];
+        if let Some(time_control) = &self.time_control {
+            tags.push(("TimeControl", time_control.clone()));
+        }
+        tags.extend(self.tags.iter().map(|(n, v)| (n.as_str(), v.clone())));
+        for (name, value) in tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{} \"{}\"]", name, value)?;
+        }
+        writeln!(f)?;
+
+        // movetext wrapped at 80 columns
+        let mut tokens = Vec::new();
+        for (i, m) in self.moves.iter().enumerate() {
+            if i % 2 == 0 {
+                tokens.push(format!("{}.", i / 2 + 1));
+            }
+            tokens.push((m.column + 1).to_string());
+            let comment = match (m.eval, &m.comment) {
+                (Some(eval), Some(comment)) => Some(format!(
+                    "[%eval {}] {}",
+                    eval.value(),
+                    escape_comment(comment)
+                )),
+                (Some(eval), None) => Some(format!("[%eval {}]", eval.value())),
+                (None, Some(comment)) => Some(escape_comment(comment)),
+                (None, None) => None,
+            };
+            if let Some(comment) = comment {
+                tokens.push(format!("{{{}}}", comment));
+            }
+        }
+        tokens.push(self.result.to_string());
+        let mut line_len = 0;
+        for token in tokens {
+            if line_len > 0 && line_len + 1 + token.len() > 80 {
+                writeln!(f)?;
+                line_len = 0;
+            }
+            if line_len > 0 {
+                write!(f, " ")?;
+                line_len += 1;
+            }
+            write!(f, "{}", token)?;
+            line_len += token.len();
+        }
+        writeln!(f)
+    }
+}
+
+// A leading `[` is escaped so that the text isn't read as an evaluation
+fn escape_comment(comment: &str) -> String {
+    let escaped = comment.replace('\\', "\\\\").replace('}', "\\}");
+    match escaped.strip_prefix('[') {
+        Some(rest) => format!("\\[{}", rest),
+        None => escaped,
+    }
+}
+
+fn unescape_comment(comment: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = comment.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+// Score of the move played at `ply`, the value must be possible at that ply
+fn parse_eval(eval: &str, ply: u64) -> Result<Score, String> {
+    let value: i32 = eval
+        .trim()
+        .parse()
+        .map_err(|_| format!("bad evaluation \"{}\"", eval))?;
+    if ply >= WIDTH * HEIGHT
+        || value > Score::win_now(ply).value()
+        || value < Score::loss_next(ply).value()
+    {
+        return Err(format!(
+            "impossible evaluation {} at ply {}",
+            value,
+            ply + 1
+        ));
+    }
+    Ok(Score::new(value, ply))
+}
+
+// Write several games separated by an empty line
+pub fn write_records(games: &[GameRecord]) -> String {
+    games
+        .iter()
+        .map(GameRecord::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Read all the games of a file
+pub fn parse_records(s: &str) -> Result<Vec<GameRecord>, String> {
+    let mut games = Vec::new();
+    let mut game = GameRecord::new();
+    // true while the current game has tags or moves
+    let mut started = false;
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '[' {
+            chars.next();
+            if !game.moves.is_empty() {
+                return Err("tag found in the middle of the moves".to_string());
+            }
+            let tag: String = chars.by_ref().take_while(|&c| c != '"').collect();
+            let name = tag.trim().to_string();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some('\\') => value.extend(chars.next()),
+                    Some('"') => break,
+                    Some(c) => value.push(c),
+                    None => return Err(format!("unterminated tag \"{}\"", name)),
+                }
+            }
+            if chars.by_ref().find(|c| !c.is_whitespace()) != Some(']') {
+                return Err(format!("unterminated tag \"{}\"", name));
+            }
+            game.set_tag(name, value)?;
+            started = true;
+        } else if c == '{' {
+            chars.next();
+            // still escaped, an escaped `[` doesn't start an evaluation
+            let mut comment = String::new();
+            loop {
+                match chars.next() {
+                    Some('\\') => {
+                        comment.push('\\');
+                        comment.extend(chars.next());
+                    }
+                    Some('}') => break,
+                    Some(c) => comment.push(c),
+                    None => return Err(format!("unterminated comment \"{}\"", comment)),
+                }
+            }
+            let ply = game.moves.len() as u64;
+            let m = match game.moves.last_mut() {
+                Some(m) => m,
+                None => return Err(format!("comment before the first move \"{}\"", comment)),
+            };
+            let mut comment = comment.trim();
+            if let Some(rest) = comment.strip_prefix("[%eval ") {
+                let (eval, rest) = rest
+                    .split_once(']')
+                    .ok_or_else(|| format!("bad evaluation \"{}\"", comment))?;
+                m.eval = Some(parse_eval(eval, ply - 1)?);
+                comment = rest.trim();
+            }
+            if !comment.is_empty() {
+                m.comment = Some(unescape_comment(comment));
+            }
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '{' || c == '[' {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            if let Ok(result) = token.parse::<GameResult>() {
+                game.result = result;
+                games.push(game);
+                game = GameRecord::new();
+                started = false;
+            } else if token.ends_with('.') && token[..token.len() - 1].parse::<u64>().is_ok() {
+                // move number
+            } else {
+                match token.parse::<u64>() {
+                    Ok(column) if column >= 1 => game.moves.push(Move::new(column - 1)),
+                    _ => return Err(format!("unexpected token \"{}\"", token)),
+                }
+                started = true;
+            }
+        }
+    }
+    if started {
+        return Err("missing result at the end of the game".to_string());
+    }
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORDS: &str = r#"[First "alice"]
+[Second "connect4"]
+[Date "2026.10.18"]
+[Result "0-1"]
+[Size "7x6"]
+[TimeControl "300+5"]
+[Event "office \"cup\""]
+
+1. 4 {[%eval 1] the best opening} 4 2. 5 {[%eval -2]} 3 {hmm} 0-1
+
+[First "bob"]
+[Second "carol"]
+[Result "*"]
+
+1. 1 2 *
+"#;
+
+    #[test]
+    fn test_parse() {
+        let games = parse_records(RECORDS).unwrap();
+        assert_eq!(games.len(), 2);
+        let game = &games[0];
+        assert_eq!(game.first, "alice");
+        assert_eq!(game.second, "connect4");
+        assert_eq!(game.date, "2026.10.18");
+        assert_eq!(game.result, GameResult::SecondWins);
+        assert_eq!((game.width, game.height), (7, 6));
+        assert_eq!(game.time_control.as_deref(), Some("300+5"));
+        assert_eq!(
+            game.tags,
+            vec![("Event".to_string(), "office \"cup\"".to_string())]
+        );
+        assert_eq!(game.columns(), vec![3, 3, 4, 2]);
+        assert_eq!(game.moves[0].eval, Some(Score::new(1, 0)));
+        assert_eq!(game.moves[0].comment.as_deref(), Some("the best opening"));
+        assert_eq!(game.moves[2].eval, Some(Score::new(-2, 2)));
+        assert_eq!(game.moves[2].comment, None);
+        assert_eq!(game.moves[3].comment.as_deref(), Some("hmm"));
+        assert_eq!(game.position().unwrap().play_count(), 4);
+
+        assert_eq!(games[1].first, "bob");
+        assert_eq!(games[1].date, "????.??.??");
+        assert_eq!(games[1].columns(), vec![0, 1]);
+
+        // scores beyond a win or a loss with the next stone
+        assert!(parse_records("1. 4 {[%eval 22]} *").is_err());
+        assert!(parse_records("1. 4 4 {[%eval -21]} *").is_err());
+        assert!(parse_records("1. 4 4 {[%eval -20]} *").is_ok());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let games = parse_records(RECORDS).unwrap();
+        let written = write_records(&games);
+        assert_eq!(parse_records(&written).unwrap(), games);
+
+        let mut long = GameRecord::new();
+        long.moves = (0..40).map(|i| Move::new(i % 7)).collect();
+        assert!(long.to_string().lines().all(|l| l.len() <= 80));
+        assert_eq!(parse_records(&long.to_string()).unwrap(), vec![long]);
+
+        let mut escaped = GameRecord::new();
+        escaped.moves = vec![Move::new(3), Move::new(3), Move::new(2)];
+        escaped.moves[0].comment = Some("a {brace} and a \\".to_string());
+        escaped.moves[1].eval = Some(Score::new(-1, 1));
+        escaped.moves[1].comment = Some("[%eval 2] is not} the score".to_string());
+        escaped.moves[2].comment = Some("[%eval 2]".to_string());
+        let written = escaped.to_string();
+        assert!(written.contains("{a {brace\\} and a \\\\}"), "{}", written);
+        assert_eq!(parse_records(&written).unwrap(), vec![escaped]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let games = parse_records(RECORDS).unwrap();
+        let json = serde_json::to_string(&games).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Vec<GameRecord>>(&json).unwrap(),
+            games
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse_records("1. 4 4").is_err());
+        assert!(parse_records("1. 4 x *").is_err());
+        assert!(parse_records("{comment} 4 *").is_err());
+        assert!(parse_records("1. 4 {comment *").is_err());
+        assert!(parse_records("[Result \"2-0\"] *").is_err());
+        assert!(parse_records("[Size \"7\"] *").is_err());
+        assert!(parse_records("[First \"alice] *").is_err());
+
+        let games = parse_records("[Size \"8x7\"] 1. 8 *").unwrap();
+        assert!(games[0].position().is_err());
+        let games = parse_records("1. 1 2 2. 1 2 3. 1 2 4. 1 2 1-0").unwrap();
+        assert!(games[0].position().is_err());
+    }
+}
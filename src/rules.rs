@@ -0,0 +1,678 @@
+// Knowledge based solver after Victor Allis, "A Knowledge-based Approach of Connect-Four" (1988)
+//
+// With the first player to move, the second player controls the zugzwang: by always answering
+// in the column the first player just played (follow-up) they get every even square. The rules
+// are local strategies derived from that idea, each one refutes some groups (four squares in a
+// row) of the first player. Rules that don't share any square and refute every group the first
+// player could still complete make a candidate proof that the second player at least draws.
+//
+// The compatibility table of Allis, which tells when solutions can be combined, isn't
+// implemented: solutions are only combined when their squares are disjoint, which isn't always
+// enough (e.g. a claimeven below an inverse in the same column). Instead each candidate proof is
+// checked by playing its strategy against every play of the first player, see `Verifier`, so a
+// returned proof is always right but some positions the thesis proves are not.
+//
+// Only draws of the second player are proven, not the rules the first player can use on odd
+// threats. With the second player to move, the proof starts with one of their plays.
+//
+// Squares are named as in the thesis: columns a to g and rows 1 to 6 from the bottom, so the
+// odd rows are the ones with an even index in the bitboards.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::position::{Position, FULL_HEIGHT, HEIGHT, WIDTH};
+
+// Bound on the combinations tried when looking for a proof
+const MAX_NODES: usize = 100_000;
+// Bound on the positions played by the checks of the candidate proofs of a position
+const MAX_VERIFIED: usize = 200_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rule {
+    /// an even square and the empty square below, the controller gets the even square
+    Claimeven,
+    /// two directly playable squares, the controller gets one of them
+    Baseinverse,
+    /// two squares above each other with the upper one odd, the controller gets one of them
+    Vertical,
+    /// a group of the controller completed with claimevens, it ends the game before any
+    /// group needing a square above it in each of its columns
+    Aftereven,
+    /// two verticals, the controller also gets one of the two upper squares
+    Lowinverse,
+    /// three empty squares in two columns with even upper squares
+    Highinverse,
+    /// two baseinverses sharing a square combined with a claimeven above it
+    Baseclaim,
+    /// a group of the controller completed before any group holding all the squares above
+    /// its empty squares, using claimevens and verticals
+    Before,
+    /// a before where a directly playable square is part of a baseinverse instead
+    Specialbefore,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 9] = [
+        Rule::Claimeven,
+        Rule::Baseinverse,
+        Rule::Vertical,
+        Rule::Aftereven,
+        Rule::Lowinverse,
+        Rule::Highinverse,
+        Rule::Baseclaim,
+        Rule::Before,
+        Rule::Specialbefore,
+    ];
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Rule::Claimeven => "claimeven",
+            Rule::Baseinverse => "baseinverse",
+            Rule::Vertical => "vertical",
+            Rule::Aftereven => "aftereven",
+            Rule::Lowinverse => "lowinverse",
+            Rule::Highinverse => "highinverse",
+            Rule::Baseclaim => "baseclaim",
+            Rule::Before => "before",
+            Rule::Specialbefore => "specialbefore",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Solution {
+    pub rule: Rule,
+    /// squares used by the rule, no other solution of a proof can use them
+    pub squares: u64,
+    /// groups of the first player refuted by the rule, as bitboards
+    pub groups: Vec<u64>,
+}
+
+// Formatted as "claimeven c1 c2"
+impl fmt::Display for Solution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.rule)?;
+        for x in 0..WIDTH {
+            for y in 0..HEIGHT {
+                if self.squares & square(x, y) != 0 {
+                    write!(f, " {}", square_name(x, y))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Rules proving that the second player at least draws
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Proof {
+    /// column played first when the second player is to move
+    pub play: Option<u64>,
+    pub solutions: Vec<Solution>,
+}
+
+// Formatted as one line per solution, after "play d" when the proof starts with a play
+impl fmt::Display for Proof {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(x) = self.play {
+            writeln!(f, "play {}", (b'a' + x as u8) as char)?;
+        }
+        for solution in &self.solutions {
+            writeln!(f, "{}", solution)?;
+        }
+        Ok(())
+    }
+}
+
+// Look for a proof that the second player at least draws, None means that the rules are not
+// enough, not that the first player wins
+pub fn prove(p: &Position) -> Option<Proof> {
+    if p.is_game_over() {
+        return None;
+    }
+    if p.play_count().is_multiple_of(2) {
+        return prove_first_to_move(p);
+    }
+    (0..WIDTH).filter(|&x| p.is_valid_play(x)).find_map(|x| {
+        let played = p.play(x);
+        let proof = if played.is_game_over() {
+            // the second player won or filled the board
+            Proof {
+                play: None,
+                solutions: Vec::new(),
+            }
+        } else {
+            prove_first_to_move(&played)?
+        };
+        Some(Proof {
+            play: Some(x),
+            ..proof
+        })
+    })
+}
+
+fn prove_first_to_move(p: &Position) -> Option<Proof> {
+    let board = Board::new(p);
+    // groups the first player can still complete
+    let threats: Vec<u64> = all_groups()
+        .into_iter()
+        .filter(|&g| g & board.second == 0)
+        .collect();
+    let candidates = board.solutions(&threats);
+
+    let mut search = Search {
+        candidates: &candidates,
+        threats: &threats,
+        nodes: 0,
+        chosen: Vec::new(),
+        position: p,
+        verified: 0,
+    };
+    // there are 69 groups on the board
+    if !search.cover(0, 0, (1 << threats.len()) - 1) {
+        return None;
+    }
+    let solutions = search
+        .chosen
+        .iter()
+        .map(|&i| {
+            let c = &candidates[i];
+            Solution {
+                rule: c.rule,
+                squares: c.squares,
+                groups: (0..threats.len())
+                    .filter(|&g| c.refuted & (1 << g) != 0)
+                    .map(|g| threats[g])
+                    .collect(),
+            }
+        })
+        .collect();
+    Some(Proof {
+        play: None,
+        solutions,
+    })
+}
+
+struct Candidate {
+    rule: Rule,
+    squares: u64,
+    /// indexes of the refuted threats
+    refuted: u128,
+}
+
+struct Board {
+    second: u64,
+    empty: u64,
+    playable: u64,
+}
+
+impl Board {
+    fn new(p: &Position) -> Board {
+        Board {
+            second: p.second_player_stones(),
+            empty: all_squares() & !p.mask(),
+            playable: playable_squares(p),
+        }
+    }
+
+    fn solutions(&self, threats: &[u64]) -> Vec<Candidate> {
+        // threats holding all the squares of `squares`
+        let refutes = |squares: u64| -> u128 {
+            threats
+                .iter()
+                .enumerate()
+                .filter(|&(_, &g)| g & squares == squares)
+                .fold(0, |acc, (i, _)| acc | 1 << i)
+        };
+        let mut solutions = Vec::new();
+        let mut push = |rule, squares, refuted| {
+            if refuted != 0 {
+                solutions.push(Candidate {
+                    rule,
+                    squares,
+                    refuted,
+                });
+            }
+        };
+
+        let empty = squares_of(self.empty);
+        let playable = squares_of(self.playable);
+        // (lower, upper) empty squares above each other
+        let claimevens: Vec<(u64, u64)> = empty
+            .iter()
+            .filter(|&&s| is_even(s) && self.empty & (s >> 1) != 0)
+            .map(|&s| (s >> 1, s))
+            .collect();
+        let verticals: Vec<(u64, u64)> = empty
+            .iter()
+            .filter(|&&s| !is_even(s) && row(s) > 0 && self.empty & (s >> 1) != 0)
+            .map(|&s| (s >> 1, s))
+            .collect();
+
+        for &(lower, upper) in &claimevens {
+            push(Rule::Claimeven, lower | upper, refutes(upper));
+        }
+        for (i, &a) in playable.iter().enumerate() {
+            for &b in &playable[i + 1..] {
+                push(Rule::Baseinverse, a | b, refutes(a | b));
+            }
+        }
+        for &(lower, upper) in &verticals {
+            push(Rule::Vertical, lower | upper, refutes(lower | upper));
+        }
+
+        for (i, &(l1, u1)) in verticals.iter().enumerate() {
+            for &(l2, u2) in &verticals[i + 1..] {
+                if column(l1) == column(l2) || refutes(u1 | u2) == 0 {
+                    continue;
+                }
+                push(
+                    Rule::Lowinverse,
+                    l1 | u1 | l2 | u2,
+                    refutes(u1 | u2) | refutes(l1 | u1) | refutes(l2 | u2),
+                );
+            }
+        }
+
+        // (lower, middle, upper) empty squares with an even upper square
+        let triples: Vec<(u64, u64, u64)> = claimevens
+            .iter()
+            .filter(|&&(lower, _)| row(lower) > 0 && self.empty & (lower >> 1) != 0)
+            .map(|&(middle, upper)| (middle >> 1, middle, upper))
+            .collect();
+        for (i, &(l1, m1, u1)) in triples.iter().enumerate() {
+            for &(l2, m2, u2) in &triples[i + 1..] {
+                if column(l1) == column(l2) {
+                    continue;
+                }
+                let mut refuted =
+                    refutes(u1 | u2) | refutes(m1 | m2) | refutes(m1 | u1) | refutes(m2 | u2);
+                if self.playable & l1 != 0 {
+                    refuted |= refutes(l1 | u2);
+                }
+                if self.playable & l2 != 0 {
+                    refuted |= refutes(l2 | u1);
+                }
+                push(Rule::Highinverse, l1 | m1 | u1 | l2 | m2 | u2, refuted);
+            }
+        }
+
+        for &b in &playable {
+            let d = b << 1;
+            if !is_even(d) || self.empty & d == 0 {
+                continue;
+            }
+            for &a in &playable {
+                for &c in &playable {
+                    if a == b || b == c || a == c {
+                        continue;
+                    }
+                    push(
+                        Rule::Baseclaim,
+                        a | b | c | d,
+                        refutes(a | d) | refutes(b | c),
+                    );
+                }
+            }
+        }
+
+        for g in all_groups() {
+            let group_empty = g & self.empty;
+            if g & !self.empty & !self.second != 0 || group_empty == 0 {
+                continue; // a stone of the first player or already complete
+            }
+            self.afterevens(group_empty, threats, &refutes, &mut push);
+            self.befores(g, group_empty, &playable, &refutes, &mut push);
+        }
+        solutions
+    }
+
+    fn afterevens(
+        &self,
+        group_empty: u64,
+        threats: &[u64],
+        refutes: &impl Fn(u64) -> u128,
+        push: &mut impl FnMut(Rule, u64, u128),
+    ) {
+        let empty = squares_of(group_empty);
+        if !empty
+            .iter()
+            .all(|&s| is_even(s) && self.empty & (s >> 1) != 0)
+        {
+            return;
+        }
+        // the squares above the highest empty square of the group in each of its columns
+        let above: Vec<u64> = (0..WIDTH)
+            .filter_map(|x| {
+                let empty_in_column = group_empty & column_squares(x);
+                if empty_in_column == 0 {
+                    return None;
+                }
+                let highest = 1 << (63 - empty_in_column.leading_zeros());
+                Some(column_squares(x) & !((highest << 1) - 1))
+            })
+            .collect();
+        let refuted = threats
+            .iter()
+            .enumerate()
+            .filter(|&(_, &g)| above.iter().all(|&a| g & a != 0))
+            .fold(0, |acc, (i, _)| acc | 1 << i)
+            | empty.iter().fold(0, |acc, &s| acc | refutes(s));
+        push(Rule::Aftereven, group_empty | group_empty >> 1, refuted);
+    }
+
+    fn befores(
+        &self,
+        group: u64,
+        group_empty: u64,
+        playable: &[u64],
+        refutes: &impl Fn(u64) -> u128,
+        push: &mut impl FnMut(Rule, u64, u128),
+    ) {
+        let empty = squares_of(group_empty);
+        // every empty square needs an empty square above it, which is not part of the group
+        if empty
+            .iter()
+            .any(|&s| row(s) == HEIGHT - 1 || group_empty & (s << 1) != 0)
+        {
+            return;
+        }
+        // claimeven if the square above is even, vertical otherwise
+        let pair = |s: u64| {
+            let above = s << 1;
+            if is_even(above) {
+                refutes(above)
+            } else {
+                refutes(s | above)
+            }
+        };
+        let above = group_empty << 1;
+        let refuted = refutes(above) | empty.iter().fold(0, |acc, &s| acc | pair(s));
+        push(Rule::Before, group_empty | above, refuted);
+
+        let columns = empty
+            .iter()
+            .fold(0, |acc, &s| acc | column_squares(column(s)));
+        for &e in empty.iter().filter(|&&e| self.playable & e != 0) {
+            for &p in playable.iter().filter(|&&p| p & (group | columns) == 0) {
+                let refuted = refutes(above | p)
+                    | refutes(e | p)
+                    | empty
+                        .iter()
+                        .filter(|&&s| s != e)
+                        .fold(0, |acc, &s| acc | pair(s));
+                push(Rule::Specialbefore, group_empty | above | p, refuted);
+            }
+        }
+    }
+}
+
+struct Search<'a> {
+    candidates: &'a [Candidate],
+    threats: &'a [u64],
+    nodes: usize,
+    chosen: Vec<usize>,
+    position: &'a Position,
+    /// positions played by the checks so far
+    verified: usize,
+}
+
+impl Search<'_> {
+    // Pick solutions with disjoint squares until every threat is refuted,
+    // starting with the threat refuted by the fewest solutions
+    fn cover(&mut self, used: u64, refuted: u128, all: u128) -> bool {
+        if refuted == all {
+            return self.verify();
+        }
+        self.nodes += 1;
+        if self.nodes > MAX_NODES {
+            return false;
+        }
+        let usable = |c: &Candidate| c.squares & used == 0;
+        let threat = (0..self.threats.len())
+            .filter(|&t| refuted & (1 << t) == 0)
+            .min_by_key(|&t| {
+                self.candidates
+                    .iter()
+                    .filter(|c| c.refuted & (1 << t) != 0 && usable(c))
+                    .count()
+            })
+            .unwrap();
+        for (i, c) in self.candidates.iter().enumerate() {
+            if c.refuted & (1 << threat) == 0 || !usable(c) {
+                continue;
+            }
+            self.chosen.push(i);
+            if self.cover(used | c.squares, refuted | c.refuted, all) {
+                return true;
+            }
+            self.chosen.pop();
+        }
+        false
+    }
+
+    fn verify(&mut self) -> bool {
+        if self.verified >= MAX_VERIFIED {
+            return false;
+        }
+        let mut verifier = Verifier {
+            solutions: self
+                .chosen
+                .iter()
+                .map(|&i| self.candidates[i].squares)
+                .collect(),
+            proven: HashSet::new(),
+            refuted: HashSet::new(),
+            nodes: 0,
+            max_nodes: MAX_VERIFIED - self.verified,
+        };
+        let proven = verifier.draws(self.position);
+        self.verified += verifier.nodes;
+        proven
+    }
+}
+
+// Plays the strategy of a candidate proof against every play of the first player. A play in the
+// squares of a solution is answered with a playable square of the same solution, any other play
+// with the follow-up if it isn't a square of a solution, and every play is tried otherwise.
+struct Verifier {
+    /// squares of each solution
+    solutions: Vec<u64>,
+    /// positions with the first player to move where the strategy works, or fails
+    proven: HashSet<u64>,
+    refuted: HashSet<u64>,
+    nodes: usize,
+    max_nodes: usize,
+}
+
+impl Verifier {
+    // True if the second player at least draws, `p` has the first player to move
+    fn draws(&mut self, p: &Position) -> bool {
+        if p.play_count() == WIDTH * HEIGHT || self.proven.contains(&p.key()) {
+            return true;
+        }
+        if self.refuted.contains(&p.key()) {
+            return false;
+        }
+        self.nodes += 1;
+        if self.nodes > self.max_nodes {
+            return false;
+        }
+        let playable = playable_squares(p);
+        let draws = (0..WIDTH).filter(|&x| p.is_valid_play(x)).all(|x| {
+            let played = p.play(x);
+            !played.opponent().is_winning() && self.answer(&played, playable & column_squares(x))
+        });
+        if draws {
+            self.proven.insert(p.key());
+        } else if self.nodes <= self.max_nodes {
+            self.refuted.insert(p.key());
+        }
+        draws
+    }
+
+    // The first player just played the square `s`
+    fn answer(&mut self, p: &Position, s: u64) -> bool {
+        if p.play_count() == WIDTH * HEIGHT {
+            return true;
+        }
+        let playable = playable_squares(p);
+        let follow_up = s << 1 & playable;
+        let in_solutions = self.solutions.iter().fold(0, |acc, &squares| acc | squares);
+        let answers = match self.solutions.iter().find(|&&squares| squares & s != 0) {
+            Some(&squares) if squares & playable != 0 => squares & playable,
+            None if follow_up & !in_solutions != 0 => follow_up,
+            _ => playable,
+        };
+        // the follow-up first, then from the left
+        let mut answers = squares_of(answers);
+        answers.sort_by_key(|&a| a != follow_up);
+        answers.into_iter().any(|a| {
+            let played = p.play(column(a));
+            played.opponent().is_winning() || self.draws(&played)
+        })
+    }
+}
+
+// Every line of four squares of the board
+fn all_groups() -> Vec<u64> {
+    let mut groups = Vec::new();
+    let line = |x: i64, y: i64, dx: i64, dy: i64| -> Option<u64> {
+        (0..4).try_fold(0, |acc, i| {
+            let (x, y) = (x + i * dx, y + i * dy);
+            if x < 0 || x >= WIDTH as i64 || y < 0 || y >= HEIGHT as i64 {
+                None
+            } else {
+                Some(acc | square(x as u64, y as u64))
+            }
+        })
+    };
+    for x in 0..WIDTH as i64 {
+        for y in 0..HEIGHT as i64 {
+            for &(dx, dy) in &[(1, 0), (0, 1), (1, 1), (1, -1)] {
+                groups.extend(line(x, y, dx, dy));
+            }
+        }
+    }
+    groups
+}
+
+fn all_squares() -> u64 {
+    (0..WIDTH).fold(0, |acc, x| acc | column_squares(x))
+}
+
+// The lowest empty square of each column
+fn playable_squares(p: &Position) -> u64 {
+    let bottom = (0..WIDTH).fold(0, |acc, x| acc | square(x, 0));
+    (p.mask() + bottom) & all_squares()
+}
+
+fn square(x: u64, y: u64) -> u64 {
+    1 << (x * FULL_HEIGHT + y)
+}
+
+fn column_squares(x: u64) -> u64 {
+    ((1 << HEIGHT) - 1) << (x * FULL_HEIGHT)
+}
+
+fn squares_of(mask: u64) -> Vec<u64> {
+    (0..64).map(|i| 1 << i).filter(|&s| mask & s != 0).collect()
+}
+
+fn column(s: u64) -> u64 {
+    s.trailing_zeros() as u64 / FULL_HEIGHT
+}
+
+fn row(s: u64) -> u64 {
+    s.trailing_zeros() as u64 % FULL_HEIGHT
+}
+
+// Rows are counted from 1 in the thesis
+fn is_even(s: u64) -> bool {
+    row(s) % 2 == 1
+}
+
+fn square_name(x: u64, y: u64) -> String {
+    format!("{}{}", (b'a' + x as u8) as char, y + 1)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(moves: &str) -> Option<Vec<Rule>> {
+        let proof = prove(&moves.parse().unwrap())?;
+        Some(proof.solutions.iter().map(|s| s.rule).collect())
+    }
+
+    #[test]
+    fn test_groups() {
+        assert_eq!(all_groups().len(), 69);
+    }
+
+    #[test]
+    fn test_prove() {
+        // all these positions are a draw or a loss for the first player
+        assert_eq!(
+            rules("142745252731633412547725551133372764"),
+            Some(vec![Rule::Baseinverse, Rule::Claimeven])
+        );
+        assert_eq!(
+            rules("127262373364512166673372734516121575"),
+            Some(vec![Rule::Aftereven])
+        );
+        assert_eq!(
+            rules("774577431732625656611173561516533432"),
+            Some(vec![Rule::Highinverse])
+        );
+        assert_eq!(
+            rules("365515215674177625726775446461144122"),
+            Some(vec![Rule::Before, Rule::Before])
+        );
+
+        // the first player wins by playing in the 4th column
+        assert!(prove(&"6125751723123151537142".parse().unwrap()).is_none());
+        assert!(prove(&"61257517231231515371424".parse().unwrap()).is_none());
+
+        // the second player to move plays first
+        let proof = prove(&"52677675164321472411331752454".parse().unwrap()).unwrap();
+        assert_eq!(proof.play, Some(1));
+        assert_eq!(
+            proof.to_string(),
+            "play b\nbefore c4 c5\nclaimeven e5 e6\nbaseinverse d6 f4\nbaseinverse a6 b6\n"
+        );
+    }
+
+    #[test]
+    fn test_data() {
+        // a proof is only found when the first player doesn't win
+        let path = format!("{}/data/Test_L2_R1", env!("CARGO_MANIFEST_DIR"));
+        let mut proven = 0;
+        for line in std::fs::read_to_string(path).unwrap().lines().take(100) {
+            let (moves, score) = line.split_once(' ').unwrap();
+            let p: Position = moves.parse().unwrap();
+            let score: i32 = score.parse().unwrap();
+            if prove(&p).is_some() {
+                proven += 1;
+                let first_score = if p.play_count().is_multiple_of(2) {
+                    score
+                } else {
+                    -score
+                };
+                assert!(first_score <= 0, "{}", moves);
+            }
+        }
+        assert!(proven > 0);
+    }
+
+    #[test]
+    fn test_display() {
+        let proof = prove(&"142745252731633412547725551133372764".parse().unwrap()).unwrap();
+        assert_eq!(proof.to_string(), "baseinverse a6 d6\nclaimeven f3 f4\n");
+        assert!(proof.solutions.iter().all(|s| !s.groups.is_empty()));
+    }
+}
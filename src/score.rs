@@ -0,0 +1,177 @@
+// Score of a position for the player to move
+//
+// A win is scored by the number of moves left to the winner when it completes four: the faster
+// the win the higher the score, from 1 for a win with the last stone up to 21 for a win with the
+// first one. Losses are negative and a draw is 0. Since the meaning of a value depends on how many
+// stones were already played, a `Score` keeps the play count of its position.
+
+use std::fmt;
+
+use crate::position::{Player, HEIGHT, WIDTH};
+use crate::solver::Outcome;
+
+const SIZE: u64 = WIDTH * HEIGHT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Score {
+    value: i32,
+    play_count: u64,
+}
+
+impl Score {
+    pub fn new(value: i32, play_count: u64) -> Score {
+        debug_assert!(play_count <= SIZE);
+        debug_assert!(
+            value <= Score::win_now(play_count).value
+                && value >= Score::loss_next(play_count).value
+        );
+        Score { value, play_count }
+    }
+
+    // The player to move wins with its next stone, the highest possible score
+    pub fn win_now(play_count: u64) -> Score {
+        Score {
+            value: ((SIZE + 1 - play_count) / 2) as i32,
+            play_count,
+        }
+    }
+
+    // The opponent wins with its next stone, the lowest possible score
+    pub fn loss_next(play_count: u64) -> Score {
+        Score {
+            value: -(((SIZE - play_count) / 2) as i32),
+            play_count,
+        }
+    }
+
+    pub fn draw(play_count: u64) -> Score {
+        Score {
+            value: 0,
+            play_count,
+        }
+    }
+
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    pub fn play_count(&self) -> u64 {
+        self.play_count
+    }
+
+    pub fn is_win(&self) -> bool {
+        self.value > 0
+    }
+
+    pub fn is_loss(&self) -> bool {
+        self.value < 0
+    }
+
+    pub fn is_draw(&self) -> bool {
+        self.value == 0
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        Outcome::from_score(self.value)
+    }
+
+    // Moves of the winner until it completes four including the winning one,
+    // for a draw the moves of the player to move until the board is full
+    pub fn moves_to_end(&self) -> u64 {
+        if self.is_loss() {
+            // the winner is the opponent, one ply later
+            (SIZE - self.play_count) / 2 + 1 - (-self.value) as u64
+        } else if self.is_win() {
+            (SIZE + 1 - self.play_count) / 2 + 1 - self.value as u64
+        } else {
+            (SIZE + 1 - self.play_count) / 2
+        }
+    }
+
+    // Plies until the winner completes four, None for a draw
+    pub fn winner_in_plies(&self) -> Option<u64> {
+        if self.is_win() {
+            Some(2 * self.moves_to_end() - 1)
+        } else if self.is_loss() {
+            Some(2 * self.moves_to_end())
+        } else {
+            None
+        }
+    }
+
+    pub fn winner(&self) -> Option<Player> {
+        let to_move = if self.play_count.is_multiple_of(2) {
+            Player::First
+        } else {
+            Player::Second
+        };
+        match self.outcome() {
+            Outcome::Win => Some(to_move),
+            Outcome::Draw => None,
+            Outcome::Loss => Some(match to_move {
+                Player::First => Player::Second,
+                Player::Second => Player::First,
+            }),
+        }
+    }
+
+    // Value from the point of view of the first player
+    pub fn absolute(&self) -> i32 {
+        if self.play_count.is_multiple_of(2) {
+            self.value
+        } else {
+            -self.value
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.outcome() {
+            Outcome::Win => write!(f, "win in {}", self.moves_to_end()),
+            Outcome::Draw => f.write_str("draw"),
+            Outcome::Loss => write!(f, "loss in {}", self.moves_to_end()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_moves_to_end() {
+        assert_eq!(Score::win_now(6).value(), 18);
+        assert_eq!(Score::win_now(6).moves_to_end(), 1);
+        assert_eq!(Score::new(18, 7).moves_to_end(), 1);
+        assert_eq!(Score::new(17, 6).moves_to_end(), 2);
+        assert_eq!(Score::new(1, SIZE - 1).moves_to_end(), 1);
+        assert_eq!(Score::new(1, SIZE - 3).moves_to_end(), 2);
+
+        // the opponent wins with its next stone
+        assert_eq!(Score::loss_next(6).moves_to_end(), 1);
+        assert_eq!(Score::loss_next(6).winner_in_plies(), Some(2));
+        assert_eq!(Score::new(-17, 6).moves_to_end(), 2);
+        assert_eq!(Score::new(17, 6).winner_in_plies(), Some(3));
+        assert_eq!(Score::draw(6).winner_in_plies(), None);
+    }
+
+    #[test]
+    fn test_perspective() {
+        let score = Score::new(-2, 9);
+        assert!(score.is_loss());
+        assert_eq!(score.winner(), Some(Player::First));
+        assert_eq!(score.absolute(), 2);
+        assert_eq!(Score::new(3, 8).winner(), Some(Player::First));
+        assert_eq!(Score::new(3, 8).absolute(), 3);
+        assert_eq!(Score::draw(8).winner(), None);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Score::new(14, 10).to_string(), "win in 3");
+        assert_eq!(Score::loss_next(10).to_string(), "loss in 1");
+        assert_eq!(Score::draw(10).to_string(), "draw");
+    }
+}
@@ -0,0 +1,353 @@
+// Minimal HTTP/1.1 JSON API on top of long lived solvers sharing their transposition table
+//
+// GET /solve?moves=4453     {"moves":"4453","score":-1,"nodes":1234,"time_us":56}
+// GET /analyze?moves=4453   {"moves":"4453","scores":[-2,-1,null,...],"nodes":1234,"time_us":56}
+// GET /bestmove?moves=4453  {"moves":"4453","bestmove":3,"score":-1,"nodes":1234,"time_us":56}
+//
+// Columns are 1 indexed as in the move strings, errors are returned as {"error":"..."}
+// Searches that don't finish within the time limit of the server are answered with a 503.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::position::Position;
+use crate::solver::{SharedCache, Solver, SolverConfig, COLUMNS_ORDER};
+
+// Connections waiting for a worker, the next ones are rejected
+const QUEUE_SIZE: usize = 64;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(10);
+
+pub struct Server {
+    listener: TcpListener,
+    workers: usize,
+    time_limit: Duration,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A, workers: usize) -> io::Result<Server> {
+        assert!(workers > 0);
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            workers,
+            time_limit: DEFAULT_TIME_LIMIT,
+        })
+    }
+
+    // Searches are aborted after `time_limit`
+    pub fn set_time_limit(&mut self, time_limit: Duration) {
+        self.time_limit = time_limit;
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn run(self) -> io::Result<()> {
+        // the transposition table is kept warm between requests
+        let cache = SharedCache::new();
+        let (tx, rx): (SyncSender<TcpStream>, Receiver<TcpStream>) = mpsc::sync_channel(QUEUE_SIZE);
+        let rx = Arc::new(Mutex::new(rx));
+        for _ in 0..self.workers {
+            let rx = rx.clone();
+            let mut worker = Worker::new(cache.clone(), self.time_limit);
+            thread::spawn(move || loop {
+                let stream = match rx.lock().unwrap().recv() {
+                    Ok(stream) => stream,
+                    Err(_) => return,
+                };
+                // the client is on its own if the connection fails
+                let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+                    let _ = handle_connection(stream, &mut worker);
+                }));
+                if handled.is_err() {
+                    worker.reset_solver();
+                }
+            });
+        }
+        for stream in self.listener.incoming() {
+            match tx.try_send(stream?) {
+                Ok(()) => (),
+                Err(TrySendError::Full(mut stream)) => {
+                    let _ = write_response(&mut stream, 503, &error_json("server is busy"));
+                }
+                Err(TrySendError::Disconnected(_)) => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+}
+
+// Each worker searches with its own solver so that requests are served in parallel
+struct Worker {
+    solver: Solver,
+    cache: SharedCache,
+    time_limit: Duration,
+    deadlines: Sender<(Instant, Arc<AtomicBool>)>,
+}
+
+impl Worker {
+    fn new(cache: SharedCache, time_limit: Duration) -> Worker {
+        Worker {
+            solver: Solver::with_shared_cache(SolverConfig::default(), cache.clone()),
+            cache,
+            time_limit,
+            deadlines: spawn_watchdog(),
+        }
+    }
+
+    // After a panic the search may have been left in any state
+    fn reset_solver(&mut self) {
+        self.solver = Solver::with_shared_cache(SolverConfig::default(), self.cache.clone());
+    }
+
+    // The search started after this call is stopped at the time limit
+    fn start_search(&mut self) {
+        let stop = Arc::new(AtomicBool::new(false));
+        self.solver.set_stop_flag(stop.clone());
+        self.solver.visited = 0;
+        // the watchdog only ends with the worker
+        self.deadlines
+            .send((Instant::now() + self.time_limit, stop))
+            .unwrap();
+    }
+}
+
+// Sets the stop flag of each search at its deadline. A new search replaces the previous one,
+// which is over since a worker runs one search at a time.
+fn spawn_watchdog() -> Sender<(Instant, Arc<AtomicBool>)> {
+    let (tx, rx) = mpsc::channel::<(Instant, Arc<AtomicBool>)>();
+    thread::spawn(move || {
+        let mut search: Option<(Instant, Arc<AtomicBool>)> = None;
+        loop {
+            let received = match &search {
+                Some((deadline, _)) => {
+                    rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match received {
+                Ok(next) => search = Some(next),
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some((_, stop)) = search.take() {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    });
+    tx
+}
+
+fn handle_connection(mut stream: TcpStream, worker: &mut Worker) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // headers are ignored
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut fields = request_line.split_ascii_whitespace();
+    let (status, body) = match (fields.next(), fields.next()) {
+        (Some("GET"), Some(target)) => route(target, worker),
+        (Some(_), Some(_)) => (405, error_json("only GET is supported")),
+        _ => (400, error_json("bad request line")),
+    };
+    write_response(&mut stream, status, &body)
+}
+
+fn route(target: &str, worker: &mut Worker) -> (u16, String) {
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (target, ""),
+    };
+    if !["/solve", "/analyze", "/bestmove"].contains(&path) {
+        return (404, error_json(&format!("unknown endpoint \"{}\"", path)));
+    }
+    let moves = query
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .find(|&(key, _)| key == "moves")
+        .map_or("", |(_, value)| value);
+    let p = match moves.parse::<Position>() {
+        Ok(p) => p,
+        Err(e) => return (400, error_json(&e)),
+    };
+    if p.opponent().is_winning() {
+        return (400, error_json("the game is over"));
+    }
+
+    worker.start_search();
+    let solver = &mut worker.solver;
+    let begin = Instant::now();
+    let result = match path {
+        "/solve" => format!("\"score\":{}", solver.solve(p).value()),
+        "/analyze" => {
+            let scores: Vec<String> = solver
+                .analyze(&p)
+                .iter()
+                .map(|s| s.map_or("null".to_string(), |s| s.value().to_string()))
+                .collect();
+            format!("\"scores\":[{}]", scores.join(","))
+        }
+        _ => {
+            let scores = solver.analyze(&p);
+            // the most central column among the best ones
+            match COLUMNS_ORDER
+                .iter()
+                .rev()
+                .filter_map(|&c| scores[c as usize].map(|s| (c, s)))
+                .max_by_key(|&(_, s)| s.value())
+            {
+                Some((c, s)) => format!("\"bestmove\":{},\"score\":{}", c + 1, s.value()),
+                None => "\"bestmove\":null,\"score\":0".to_string(),
+            }
+        }
+    };
+    if solver.stopped() {
+        let message = format!("no result within {:?}", worker.time_limit);
+        return (503, error_json(&message));
+    }
+    let body = format!(
+        "{{\"moves\":{},{},\"nodes\":{},\"time_us\":{}}}",
+        json_string(moves),
+        result,
+        solver.visited,
+        begin.elapsed().as_micros()
+    );
+    (200, body)
+}
+
+fn write_response(stream: &mut TcpStream, status: u16, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Service Unavailable",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn error_json(message: &str) -> String {
+    format!("{{\"error\":{}}}", json_string(message))
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    // the player to move can only win by playing in the 4th column
+    const PUZZLE: &str = "6125751723123151537142";
+
+    fn start() -> SocketAddr {
+        start_with(DEFAULT_TIME_LIMIT)
+    }
+
+    fn start_with(time_limit: Duration) -> SocketAddr {
+        let mut server = Server::bind("127.0.0.1:0", 2).unwrap();
+        server.set_time_limit(time_limit);
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+        addr
+    }
+
+    fn get(addr: SocketAddr, target: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split("\r\n\r\n").nth(1).unwrap().to_string();
+        (status, body)
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let addr = start();
+        let (status, body) = get(addr, &format!("/solve?moves={}", PUZZLE));
+        assert_eq!(status, 200);
+        assert!(body.starts_with(&format!("{{\"moves\":\"{}\",\"score\":10,", PUZZLE)));
+
+        let (status, body) = get(addr, &format!("/analyze?moves={}", PUZZLE));
+        assert_eq!(status, 200);
+        assert!(
+            body.contains("\"scores\":[null,-10,-10,10,-10,-10,-10]"),
+            "{}",
+            body
+        );
+
+        let (status, body) = get(addr, &format!("/bestmove?moves={}", PUZZLE));
+        assert_eq!(status, 200);
+        assert!(body.contains("\"bestmove\":4,\"score\":10"), "{}", body);
+    }
+
+    #[test]
+    fn test_errors() {
+        let addr = start();
+        assert_eq!(get(addr, "/solve?moves=8").0, 400);
+        assert_eq!(get(addr, &format!("/solve?moves={}4", PUZZLE)).0, 400);
+        assert_eq!(get(addr, "/foo").0, 404);
+        assert_eq!(get(addr, "/foo?moves=8").0, 404);
+        let (status, body) = get(addr, "/solve?moves=a\"");
+        assert_eq!(status, 400);
+        assert_eq!(
+            body,
+            "{\"error\":\"bad position string format \\\"a\\\"\\\"\"}"
+        );
+    }
+
+    #[test]
+    fn test_time_limit() {
+        let addr = start_with(Duration::from_secs(1));
+        // the empty board can't be solved within the time limit
+        let slow = thread::spawn(move || get(addr, "/solve?moves="));
+        thread::sleep(Duration::from_millis(50));
+        // served by the other worker meanwhile
+        let begin = Instant::now();
+        let (status, body) = get(addr, &format!("/solve?moves={}", PUZZLE));
+        assert_eq!(status, 200, "{}", body);
+        assert!(begin.elapsed() < Duration::from_secs(1));
+        let (status, body) = slow.join().unwrap();
+        assert_eq!(status, 503);
+        assert_eq!(body, "{\"error\":\"no result within 1s\"}");
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\u000a\"");
+    }
+}
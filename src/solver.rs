@@ -1,45 +1,254 @@
+use std::fmt;
+
 use bitfield_struct::bitfield;
 
-use crate::position::{Position, HEIGHT, WIDTH};
+use crate::position::{Position, FULL_HEIGHT, HEIGHT, WIDTH};
+use crate::score::Score;
+use crate::tablebase::Tablebase;
 
-const COLUMNS_ORDER: [u64; 7] = [3, 2, 4, 1, 5, 0, 6];
+pub const COLUMNS_ORDER: [u64; 7] = [3, 2, 4, 1, 5, 0, 6];
 
 pub struct Solver {
     pub visited: usize,
     cache: Cache,
+    stop: Arc<AtomicBool>,
+    tablebase: Option<Arc<Tablebase>>,
+    config: SolverConfig,
+    /// two columns that caused a cutoff for each play count
+    killers: [[u64; 2]; (WIDTH * HEIGHT) as usize],
+    /// cutoffs caused by playing in each cell, for each player
+    history: [[u64; (WIDTH * FULL_HEIGHT) as usize]; 2],
+    /// of the current `solve_with_stats`
+    stats: SearchStats,
+    root_play_count: u64,
+}
+
+// How `solve` narrows the score down with calls to `solve_rec`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RootStrategy {
+    /// null windows bisecting the possible scores, closer to 0 first
+    #[default]
+    Bisection,
+    /// null windows moving from a guess towards the score one bound at a time
+    Mtdf,
+    /// a narrow window around a guess, widened on the failing side if needed
+    Aspiration,
+    /// a single search with the window of all the possible scores
+    FullWindow,
+}
+
+// Half width of the first window of `RootStrategy::Aspiration`
+const ASPIRATION_WINDOW: i32 = 2;
+
+// Deeper than the root, symmetric positions are only looked for before this ply,
+// later they are too rare to pay for the check at every node
+const MAX_SYMMETRIC_PLY: u64 = 12;
+
+// Move ordering heuristics tried before the threat count of `Position::score`
+// and other search options, all disabled by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SolverConfig {
+    pub root: RootStrategy,
+    /// try first the best play stored in the transposition table
+    pub tt_play: bool,
+    /// then the plays that caused a cutoff in positions with the same play count
+    pub killers: bool,
+    /// break the threat count ties with the cutoffs caused in the same cell
+    pub history: bool,
+    /// look up every child in the transposition table for a cutoff before searching any
+    pub enhanced_cutoffs: bool,
+}
+
+// Statistics of a search
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchStats {
+    /// positions explored by `solve_rec`
+    pub nodes: usize,
+    /// positions explored at each number of plies from the root
+    pub nodes_per_depth: Vec<usize>,
+    /// transposition table lookups that found the position
+    pub tt_hits: usize,
+    pub tt_misses: usize,
+    /// transposition table stores that replaced another position
+    pub tt_overwrites: usize,
+    /// plays scoring at least beta, the other plays are not searched
+    pub cutoffs: usize,
+    /// cutoffs by the first play searched
+    pub first_move_cutoffs: usize,
+    /// searches of the root with a window of width 1
+    pub null_window_searches: usize,
+    pub elapsed: Duration,
+}
+
+impl SearchStats {
+    // Share of the cutoffs found with the first play, 1 with a perfect move ordering
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        if self.cutoffs == 0 {
+            return 0.0;
+        }
+        self.first_move_cutoffs as f64 / self.cutoffs as f64
+    }
+
+    // Branching factor of a uniform tree as deep as the search with as many nodes per search of
+    // the root
+    pub fn effective_branching_factor(&self) -> f64 {
+        let depth = self.nodes_per_depth.len().saturating_sub(1);
+        if depth == 0 {
+            return 1.0;
+        }
+        let tree_size = self.nodes as f64 / self.nodes_per_depth[0] as f64;
+        tree_size.powf(1.0 / depth as f64)
+    }
+
+    // Accumulate the statistics of another search
+    pub fn add(&mut self, other: &SearchStats) {
+        self.nodes += other.nodes;
+        if self.nodes_per_depth.len() < other.nodes_per_depth.len() {
+            self.nodes_per_depth.resize(other.nodes_per_depth.len(), 0);
+        }
+        for (total, nodes) in self.nodes_per_depth.iter_mut().zip(&other.nodes_per_depth) {
+            *total += nodes;
+        }
+        self.tt_hits += other.tt_hits;
+        self.tt_misses += other.tt_misses;
+        self.tt_overwrites += other.tt_overwrites;
+        self.cutoffs += other.cutoffs;
+        self.first_move_cutoffs += other.first_move_cutoffs;
+        self.null_window_searches += other.null_window_searches;
+        self.elapsed += other.elapsed;
+    }
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lookups = (self.tt_hits + self.tt_misses).max(1) as f64;
+        writeln!(f, "nodes: {} in {:.2?}", self.nodes, self.elapsed)?;
+        writeln!(f, "nodes per depth: {:?}", self.nodes_per_depth)?;
+        writeln!(
+            f,
+            "transposition table: {} hits ({:.1}%), {} misses, {} overwrites",
+            self.tt_hits,
+            100.0 * self.tt_hits as f64 / lookups,
+            self.tt_misses,
+            self.tt_overwrites
+        )?;
+        writeln!(
+            f,
+            "cutoffs: {}, {:.1}% by the first play",
+            self.cutoffs,
+            100.0 * self.first_move_cutoff_rate()
+        )?;
+        writeln!(
+            f,
+            "effective branching factor: {:.2}",
+            self.effective_branching_factor()
+        )?;
+        write!(f, "null window searches: {}", self.null_window_searches)
+    }
+}
+
+// Result of a game with perfect play, from the point of view of the player to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+impl Outcome {
+    pub fn from_score(score: i32) -> Outcome {
+        match score.signum() {
+            1 => Outcome::Win,
+            0 => Outcome::Draw,
+            _ => Outcome::Loss,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Outcome::Win => "win",
+            Outcome::Draw => "draw",
+            Outcome::Loss => "loss",
+        })
+    }
 }
 
 const CACHE_SIZE: usize = 9_500_000 / 8; // L2 cache is 9.5MB
 
 #[bitfield(u64, default = true)]
 struct CacheEntry {
-    #[bits(56)]
-    key: u64, // a position only needs 56bits to be represented
+    #[bits(52)]
+    key: u64, // a position only needs 49bits to be represented
+    #[bits(4)]
+    play: u64, // best column + 1, 0 if unknown
     #[bits(8)]
     value: i32,
 }
 
-use std::sync::RwLock;
+// Transposition table, chosen when the solver is built
+enum Cache {
+    /// owned by a single solver, accessed without any synchronization
+    Local(Vec<CacheEntry>),
+    /// shared by solvers searching in parallel, entries are read and written atomically
+    Shared(SharedCache),
+}
 
-struct Cache(RwLock<Vec<CacheEntry>>);
+// Transposition table that several solvers can use at the same time, see `Solver::with_shared_cache`
+#[derive(Clone)]
+pub struct SharedCache(Arc<[AtomicU64]>);
 
-impl Cache {
-    pub fn new() -> Cache {
-        Cache(RwLock::new(vec![Default::default(); CACHE_SIZE]))
+impl SharedCache {
+    pub fn new() -> SharedCache {
+        SharedCache((0..CACHE_SIZE).map(|_| AtomicU64::new(0)).collect())
     }
 
-    pub fn insert(&mut self, key: u64, value: i32) {
-        self.0.write().unwrap()[Cache::index(key)] =
-            CacheEntry::new().with_key(key).with_value(value);
+    // Remove the entries of all the solvers using this cache, `Solver::reset` keeps them
+    pub fn clear(&self) {
+        self.0
+            .iter()
+            .for_each(|entry| entry.store(0, Ordering::Relaxed));
     }
+}
 
-    pub fn get(&self, key: u64) -> i32 {
-        let entry = &self.0.read().unwrap()[Cache::index(key)];
-        return if entry.key() == key { entry.value() } else { 0 };
+impl Default for SharedCache {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    pub fn clear(&mut self) {
-        self.0.write().unwrap().fill(Default::default());
+impl Cache {
+    // True if the entry of another position was replaced
+    pub fn insert(&mut self, key: u64, value: i32, play: Option<u64>) -> bool {
+        let entry = CacheEntry::new()
+            .with_key(key)
+            .with_play(play.map_or(0, |c| c + 1))
+            .with_value(value);
+        let previous = match self {
+            Cache::Local(entries) => std::mem::replace(&mut entries[Cache::index(key)], entry),
+            Cache::Shared(SharedCache(entries)) => {
+                CacheEntry::from(entries[Cache::index(key)].swap(entry.into(), Ordering::Relaxed))
+            }
+        };
+        previous.key() != key && u64::from(previous) != 0
+    }
+
+    // Upper bound of the score, 0 if unknown, and best play
+    pub fn get(&self, key: u64) -> Option<(i32, Option<u64>)> {
+        let entry = match self {
+            Cache::Local(entries) => entries[Cache::index(key)],
+            Cache::Shared(SharedCache(entries)) => {
+                CacheEntry::from(entries[Cache::index(key)].load(Ordering::Relaxed))
+            }
+        };
+        if entry.key() == key {
+            Some((entry.value(), entry.play().checked_sub(1)))
+        } else {
+            None
+        }
     }
 
     fn index(key: u64) -> usize {
@@ -80,46 +289,162 @@ impl PlaySorter {
     }
 }
 
-use std::sync::{mpsc, Arc};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 impl Solver {
     pub fn new() -> Solver {
+        Solver::with_config(SolverConfig::default())
+    }
+
+    pub fn with_config(config: SolverConfig) -> Solver {
+        Solver::with_cache(config, Cache::Local(vec![Default::default(); CACHE_SIZE]))
+    }
+
+    // The transposition table is shared with the other solvers built with `cache`
+    pub fn with_shared_cache(config: SolverConfig, cache: SharedCache) -> Solver {
+        Solver::with_cache(config, Cache::Shared(cache))
+    }
+
+    fn with_cache(config: SolverConfig, cache: Cache) -> Solver {
         Solver {
             visited: 0,
-            cache: Cache::new(),
+            cache,
+            stop: Arc::new(AtomicBool::new(false)),
+            tablebase: None,
+            config,
+            killers: [[WIDTH; 2]; (WIDTH * HEIGHT) as usize],
+            history: [[0; (WIDTH * FULL_HEIGHT) as usize]; 2],
+            stats: SearchStats::default(),
+            root_play_count: 0,
         }
     }
 
-    pub fn best_play(&mut self, p: Position) -> u64 {
-        let (tx, rx) = mpsc::channel();
-        // let self_rc = Arc::new(self);
-        for &c in COLUMNS_ORDER.iter().rev() {
-            let played = p.play(c).opponent();
-            let tx = tx.clone();
-            // TODO: pass the cache as an argument wrapped in Arc<RwLock<Cache>>
-            // let self_rc = self_rc.clone();
-            // std::thread::spawn(move || {
-            let s = self.solve(played);
-            tx.send((c, s)).unwrap();
-            println!("col: {c}, score: {s}");
-            // });
+    pub fn config(&self) -> SolverConfig {
+        self.config
+    }
+
+    // The search is aborted as soon as `stop` is set,
+    // the scores returned after that are meaningless (see `stopped`)
+    pub fn set_stop_flag(&mut self, stop: Arc<AtomicBool>) {
+        self.stop = stop;
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    // Positions found in the tablebase are not searched
+    pub fn set_tablebase(&mut self, tablebase: Arc<Tablebase>) {
+        self.tablebase = Some(tablebase);
+    }
+
+    // Most central of the best columns, None if the game is over
+    pub fn best_play(&mut self, p: &Position) -> Option<u64> {
+        if p.opponent().is_winning() {
+            return None;
         }
-        drop(tx);
-        rx.iter().max_by_key(|&(_, score)| score).unwrap().0
+        let scores = self.analyze(p);
+        COLUMNS_ORDER
+            .iter()
+            .rev()
+            .filter_map(|&c| scores[c as usize].map(|s| (c, s)))
+            .max_by_key(|&(_, s)| s.value())
+            .map(|(c, _)| c)
     }
 
-    pub fn solve(&mut self, p: Position) -> i32 {
+    // Score of each column from the point of view of the player to move,
+    // None if the column is full
+    pub fn analyze(&mut self, p: &Position) -> [Option<Score>; WIDTH as usize] {
+        let mut scores = [None; WIDTH as usize];
+        let symmetric = p.is_symmetric();
+        for c in (0..WIDTH).filter(|&c| p.is_valid_play(c)) {
+            scores[c as usize] = if symmetric && c > WIDTH / 2 {
+                scores[(WIDTH - 1 - c) as usize]
+            } else {
+                Some(self.solve_play(p, c))
+            };
+        }
+        scores
+    }
+
+    // Score of playing in `col_pos` from the point of view of the player to move
+    pub fn solve_play(&mut self, p: &Position, col_pos: u64) -> Score {
+        let played = p.play(col_pos);
+        if played.opponent().is_winning() {
+            Score::win_now(p.play_count())
+        } else {
+            Score::new(-self.solve(played).value(), p.play_count())
+        }
+    }
+
+    // Sequence of best plays of both players until the end of the game.
+    // The winner plays the fastest win and the loser delays it as much as possible.
+    pub fn principal_variation(&mut self, mut p: Position) -> Vec<u64> {
+        let mut line = Vec::new();
+        // stop when the last play won or the board is full
+        while let Some(c) = self.best_play(&p) {
+            if self.stopped() {
+                break;
+            }
+            line.push(c);
+            p = p.play(c);
+        }
+        line
+    }
+
+    // Like `solve` but also returns the statistics of the search
+    pub fn solve_with_stats(&mut self, p: Position) -> (Score, SearchStats) {
+        self.stats = SearchStats::default();
+        let visited = self.visited;
+        let begin = Instant::now();
+        let score = self.solve(p);
+        let mut stats = std::mem::take(&mut self.stats);
+        stats.nodes = self.visited - visited;
+        stats.elapsed = begin.elapsed();
+        (score, stats)
+    }
+
+    // Score of the position for the player to move
+    pub fn solve(&mut self, p: Position) -> Score {
+        if p.is_winning() {
+            // the player to move already completed four
+            return Score::win_now(p.play_count());
+        }
+        Score::new(self.solve_value(p), p.play_count())
+    }
+
+    fn solve_value(&mut self, mut p: Position) -> i32 {
         if p.is_winning() {
             // 1+ to add more weight compared to can_win_next
-            return 1 + ((WIDTH * HEIGHT + 1 - p.play_count) / 2) as i32;
+            return 1 + Score::win_now(p.play_count()).value();
         }
         if p.can_win_next() {
-            return ((WIDTH * HEIGHT + 1 - p.play_count) / 2) as i32;
+            return Score::win_now(p.play_count()).value();
+        }
+        let min = Score::loss_next(p.play_count()).value();
+        let max = Score::win_now(p.play_count()).value();
+        self.root_play_count = p.play_count();
+        match self.config.root {
+            RootStrategy::Bisection => self.bisection(p, min, max),
+            RootStrategy::Mtdf => {
+                let guess = Self::guess(&p).clamp(min, max);
+                self.mtdf(p, min, max, guess)
+            }
+            RootStrategy::Aspiration => {
+                let guess = Self::guess(&p).clamp(min, max);
+                self.aspiration(p, min, max, guess)
+            }
+            RootStrategy::FullWindow => {
+                let opponent_win_mask = p.opponent_winning_mask();
+                self.solve_rec(&mut p, opponent_win_mask, min, max)
+            }
         }
-        // TODO: return directly if can win next move (otherwise solve_rec fails since it trims the
-        // winning move out)
-        let mut min = -((WIDTH * HEIGHT - p.play_count) as i32) / 2;
-        let mut max = (WIDTH * HEIGHT + 1 - p.play_count) as i32 / 2;
+    }
+
+    fn bisection(&mut self, mut p: Position, mut min: i32, mut max: i32) -> i32 {
+        let opponent_win_mask = p.opponent_winning_mask();
         // Iterative deepening
         // -------------------
         // Increase the search depth step by step
@@ -142,7 +467,7 @@ impl Solver {
         //
         // `mid` starts at 0 and then become larger or greater. Meaning we start at low depth and
         // explore more and more deep in one direction (winning/+ or losing/-).
-        while min < max {
+        while min < max && !self.stopped() {
             // Compute mid according to min,max to in a dichotomic search fashion
             let mut mid = min + (max - min) / 2;
             if mid <= 0 && min / 2 < mid {
@@ -151,7 +476,8 @@ impl Solver {
                 mid = max / 2;
             }
             // Check if actual score is greater or lower than mid
-            let shallow_score = self.solve_rec(p.clone(), mid, mid + 1);
+            self.stats.null_window_searches += 1;
+            let shallow_score = self.solve_rec(&mut p, opponent_win_mask, mid, mid + 1);
             // Reduce the min,max bounds according to shallow score
             if shallow_score > mid {
                 min = shallow_score;
@@ -159,24 +485,102 @@ impl Solver {
                 max = shallow_score;
             }
         }
-        return min;
+        min
+    }
+
+    // MTD(f): each null window search around the last result moves one of the bounds
+    fn mtdf(&mut self, mut p: Position, mut min: i32, mut max: i32, guess: i32) -> i32 {
+        let opponent_win_mask = p.opponent_winning_mask();
+        let mut score = guess;
+        while min < max && !self.stopped() {
+            let beta = score.clamp(min + 1, max);
+            self.stats.null_window_searches += 1;
+            score = self.solve_rec(&mut p, opponent_win_mask, beta - 1, beta);
+            if score < beta {
+                max = score;
+            } else {
+                min = score;
+            }
+        }
+        min
+    }
+
+    fn aspiration(&mut self, mut p: Position, min: i32, max: i32, guess: i32) -> i32 {
+        let opponent_win_mask = p.opponent_winning_mask();
+        let mut alpha = (guess - ASPIRATION_WINDOW).max(min);
+        let mut beta = (guess + ASPIRATION_WINDOW).min(max);
+        loop {
+            let score = self.solve_rec(&mut p, opponent_win_mask, alpha, beta);
+            if self.stopped() {
+                return score;
+            }
+            if score <= alpha && alpha > min {
+                // the score is at most `alpha`
+                beta = alpha + 1;
+                alpha = min;
+            } else if score >= beta && beta < max {
+                alpha = beta - 1;
+                beta = max;
+            } else {
+                return score;
+            }
+        }
+    }
+
+    // The player with more threats is likely to win, a draw otherwise
+    fn guess(p: &Position) -> i32 {
+        p.score() as i32 - p.opponent().score() as i32
     }
 
     // The weak solver only tells if the position is a win/lose/draw
     // it's faster but less precise
-    pub fn solve_weak(&mut self, p: Position) -> i32 {
-        self.solve_rec(p, -1, 1)
+    pub fn solve_weak(&mut self, mut p: Position) -> Outcome {
+        if p.opponent().is_winning() {
+            return Outcome::Loss;
+        }
+        if p.can_win_next() {
+            return Outcome::Win;
+        }
+        if p.play_count() == WIDTH * HEIGHT {
+            return Outcome::Draw;
+        }
+        // a [-1, 1] window is enough to know the sign of the score
+        self.root_play_count = p.play_count();
+        let opponent_win_mask = p.opponent_winning_mask();
+        Outcome::from_score(self.solve_rec(&mut p, opponent_win_mask, -1, 1))
     }
 
-    fn solve_rec(&mut self, p: Position, mut alpha: i32, mut beta: i32) -> i32 {
+    // `p` is played on in place and restored before returning, the winning cells of the
+    // opponent are found by the parent when it orders its plays
+    fn solve_rec(
+        &mut self,
+        p: &mut Position,
+        opponent_win_mask: u64,
+        mut alpha: i32,
+        mut beta: i32,
+    ) -> i32 {
         debug_assert!(alpha < beta);
         debug_assert!(!p.can_win_next());
         self.visited += 1;
+        let depth = (p.play_count() - self.root_play_count) as usize;
+        if self.stats.nodes_per_depth.len() <= depth {
+            self.stats.nodes_per_depth.resize(depth + 1, 0);
+        }
+        self.stats.nodes_per_depth[depth] += 1;
+        if self.stopped() {
+            return alpha;
+        }
+        if let Some(score) = self.tablebase.as_ref().and_then(|t| t.get(p)) {
+            return score.value();
+        }
 
-        let non_losing_play_mask = p.possible_non_losing_play_mask();
+        let mut non_losing_play_mask = p.non_losing_plays(opponent_win_mask);
         if non_losing_play_mask == 0 {
-            // not width*height [+ 1] because it's one less move
-            return -(((WIDTH * HEIGHT) as i32) - (p.play_count as i32)) / 2;
+            return Score::loss_next(p.play_count()).value();
+        }
+        if (depth == 0 || p.play_count() < MAX_SYMMETRIC_PLY) && p.is_symmetric() {
+            // the mirrored plays have the same scores
+            non_losing_play_mask &= !Position::right_half_mask();
         }
 
         if p.is_draw() {
@@ -184,7 +588,8 @@ impl Solver {
         }
 
         // This copy paste made a huge difference, hmmm
-        let min = -(((WIDTH * HEIGHT - 2 - p.play_count) / 2) as i32); // lower bound of score as opponent cannot win next move
+        // lower bound of score as opponent cannot win next move
+        let min = Score::loss_next(p.play_count() + 2).value();
         if alpha < min {
             alpha = min; // there is no need to keep beta above our max possible score.
             if alpha >= beta {
@@ -192,7 +597,16 @@ impl Solver {
             } // prune the exploration if the [alpha;beta] window is empty.
         }
 
-        let max_score = self.cache.get(p.key());
+        let (max_score, tt_play) = match self.cache.get(p.key()) {
+            Some(entry) => {
+                self.stats.tt_hits += 1;
+                entry
+            }
+            None => {
+                self.stats.tt_misses += 1;
+                (0, None)
+            }
+        };
         if max_score != 0 {
             // can't return max_score directly
             // because the alpha-beta context in the cache may be
@@ -205,39 +619,132 @@ impl Solver {
             }
         }
 
+        if self.config.enhanced_cutoffs {
+            if let Some(score) = self.enhanced_cutoff(p, non_losing_play_mask, beta) {
+                return score;
+            }
+        }
+
         let mut sorter = PlaySorter::new();
+        let mut child_win_masks = [0; WIDTH as usize];
         COLUMNS_ORDER
             .iter()
-            .filter(|&&x| p.is_valid_play(x))
-            .for_each(|&x| sorter.insert(x, p.play(x).opponent().score()));
+            .filter(|&&x| Position::column_mask(x) & non_losing_play_mask != 0)
+            .for_each(|&x| {
+                let play = non_losing_play_mask & Position::column_mask(x);
+                let child_win_mask = p.played_winning_mask(play);
+                child_win_masks[x as usize] = child_win_mask;
+                sorter.insert(x, self.play_order(p, x, play, child_win_mask, tt_play))
+            });
         let mut best = alpha;
+        let mut best_play = None;
+        let mut first = true;
         while let Some(x) = sorter.pop() {
-            let played = p.play(x);
-            if Position::column_mask(x) & non_losing_play_mask == 0 {
-                continue;
-            }
             // using negamax, variante of minimax where:
             // max(player1, player2) == -min(-player1, -player2)
-            let score = -self.solve_rec(played, -beta, -alpha);
+            let play = non_losing_play_mask & Position::column_mask(x);
+            p.make_play(play);
+            let score = -self.solve_rec(p, child_win_masks[x as usize], -beta, -alpha);
+            p.unmake_play(play);
             if score > best {
                 best = score;
+                best_play = Some(x);
                 // reduce alpha-beta range if found better score
                 if best > alpha {
                     alpha = best;
                 }
                 // impossible alpha-beta range reached (alpha is supposed to be < to beta)
                 if alpha >= beta {
+                    self.stats.cutoffs += 1;
+                    if first {
+                        self.stats.first_move_cutoffs += 1;
+                    }
+                    if !self.stopped() {
+                        self.record_cutoff(p, x, play, max_score);
+                    }
                     return score;
                 }
             }
+            first = false;
+        }
+        // a child search may have been aborted, making `best` wrong
+        if !self.stopped() && self.cache.insert(p.key(), best, best_play) {
+            self.stats.tt_overwrites += 1;
+        }
+        best
+    }
+
+    // A child whose upper bound is low enough proves that `p` scores at least `beta`
+    fn enhanced_cutoff(&self, p: &Position, non_losing_play_mask: u64, beta: i32) -> Option<i32> {
+        (0..WIDTH)
+            .filter(|&x| Position::column_mask(x) & non_losing_play_mask != 0)
+            .map(|x| {
+                self.cache
+                    .get(p.play(x).key())
+                    .map_or(0, |(child_max, _)| child_max)
+            })
+            .filter(|&child_max| child_max != 0)
+            .map(|child_max| -child_max)
+            .find(|&score| score >= beta)
+    }
+
+    // Plays are searched by decreasing order, see `SolverConfig`
+    fn play_order(
+        &self,
+        p: &Position,
+        x: u64,
+        play: u64,
+        child_win_mask: u64,
+        tt_play: Option<u64>,
+    ) -> u64 {
+        // the winning cells of the player after its play
+        let mut order = (child_win_mask.count_ones() as u64) << 32;
+        if self.config.tt_play && tt_play == Some(x) {
+            order |= 1 << 63;
+        }
+        if self.config.killers && self.killers[p.play_count() as usize].contains(&x) {
+            order |= 1 << 31;
+        }
+        if self.config.history {
+            order |= self.history[p.play_count() as usize % 2][play.trailing_zeros() as usize]
+                .min((1 << 31) - 1);
+        }
+        order
+    }
+
+    fn record_cutoff(&mut self, p: &Position, x: u64, play: u64, max_score: i32) {
+        if self.config.tt_play {
+            // keep the upper bound already known
+            if self.cache.insert(p.key(), max_score, Some(x)) {
+                self.stats.tt_overwrites += 1;
+            }
+        }
+        let ply = p.play_count() as usize;
+        if self.config.killers && self.killers[ply][0] != x {
+            self.killers[ply] = [x, self.killers[ply][0]];
+        }
+        if self.config.history {
+            let depth = WIDTH * HEIGHT - p.play_count();
+            self.history[ply % 2][play.trailing_zeros() as usize] += depth * depth;
         }
-        self.cache.insert(p.key(), best);
-        return best;
     }
 
+    // Forget the previous searches. A shared cache is left to the other solvers using it,
+    // see `SharedCache::clear`.
     pub fn reset(&mut self) {
         self.visited = 0;
-        self.cache.clear();
+        if let Cache::Local(entries) = &mut self.cache {
+            entries.fill(Default::default());
+        }
+        self.killers = [[WIDTH; 2]; (WIDTH * HEIGHT) as usize];
+        self.history = [[0; (WIDTH * FULL_HEIGHT) as usize]; 2];
+        self.stats = SearchStats::default();
+    }
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -256,3 +763,164 @@ mod play_sorter_test {
         assert_eq!(s.pop(), None);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // the first positions of a data file with the outcome of their score
+    fn data_outcomes(name: &str, count: usize) -> Vec<(Position, Outcome)> {
+        let path = format!("{}/data/{}", env!("CARGO_MANIFEST_DIR"), name);
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .take(count)
+            .map(|line| {
+                let (moves, score) = line.split_once(' ').unwrap();
+                (
+                    moves.parse().unwrap(),
+                    Outcome::from_score(score.parse().unwrap()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_solve_weak() {
+        let mut solver = Solver::new();
+        for name in ["Test_L3_R1", "Test_L2_R1"] {
+            for (p, outcome) in data_outcomes(name, 50) {
+                assert_eq!(solver.solve_weak(p), outcome, "{:?}", p);
+            }
+        }
+    }
+
+    #[test]
+    fn test_config() {
+        let positions = data_outcomes("Test_L2_R1", 20);
+        let mut baseline = Solver::new();
+        let scores: Vec<Score> = positions.iter().map(|(p, _)| baseline.solve(*p)).collect();
+        for root in [
+            RootStrategy::Bisection,
+            RootStrategy::Mtdf,
+            RootStrategy::Aspiration,
+            RootStrategy::FullWindow,
+        ] {
+            let config = SolverConfig {
+                root,
+                tt_play: true,
+                killers: true,
+                history: true,
+                enhanced_cutoffs: true,
+            };
+            let mut solver = Solver::with_config(config);
+            for ((p, _), &score) in positions.iter().zip(&scores) {
+                assert_eq!(solver.solve(*p), score, "{:?} {:?}", root, p);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_cache() {
+        let positions = data_outcomes("Test_L2_R1", 20);
+        let mut local = Solver::new();
+        let scores: Vec<Score> = positions.iter().map(|&(p, _)| local.solve(p)).collect();
+
+        let cache = SharedCache::new();
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let cache = cache.clone();
+                let positions = positions.clone();
+                std::thread::spawn(move || {
+                    let mut solver = Solver::with_shared_cache(SolverConfig::default(), cache);
+                    positions
+                        .iter()
+                        .map(|&(p, _)| solver.solve(p))
+                        .collect::<Vec<Score>>()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), scores);
+        }
+
+        // the entries of the other solvers are used
+        let mut solver = Solver::with_shared_cache(SolverConfig::default(), cache.clone());
+        let (p, _) = positions[0];
+        assert_eq!(solver.solve(p), scores[0]);
+        let mut fresh = Solver::new();
+        fresh.solve(p);
+        assert!(solver.visited < fresh.visited);
+
+        // reset keeps the entries of the shared cache, only clear removes them
+        solver.reset();
+        solver.solve(p);
+        assert!(solver.visited < fresh.visited);
+        cache.clear();
+        solver.reset();
+        solver.solve(p);
+        assert_eq!(solver.visited, fresh.visited);
+    }
+
+    #[test]
+    fn test_symmetric() {
+        let p: Position = "15734147751312766622".parse().unwrap();
+        assert!(p.is_symmetric());
+        let mut solver = Solver::new();
+        let scores = solver.analyze(&p);
+        for c in 0..WIDTH {
+            assert_eq!(scores[c as usize], scores[(WIDTH - 1 - c) as usize]);
+        }
+        assert_eq!(scores.iter().flatten().map(|s| s.value()).max(), Some(1));
+        solver.reset();
+        let score = solver.solve(p);
+        assert_eq!((score.value(), score.play_count()), (1, p.play_count()));
+    }
+
+    #[test]
+    fn test_best_play() {
+        let mut solver = Solver::new();
+        // only the 4th column wins
+        let p: Position = "6125751723123151537142".parse().unwrap();
+        assert_eq!(solver.best_play(&p), Some(3));
+        let p: Position = "1212121".parse().unwrap();
+        assert_eq!(solver.best_play(&p), None);
+    }
+
+    #[test]
+    fn test_search_stats() {
+        let (p, _) = data_outcomes("Test_L2_R1", 1)[0];
+        let mut solver = Solver::new();
+        let (_, stats) = solver.solve_with_stats(p);
+        assert_eq!(stats.nodes, solver.visited);
+        assert_eq!(stats.nodes_per_depth.iter().sum::<usize>(), stats.nodes);
+        assert_eq!(stats.nodes_per_depth[0], stats.null_window_searches);
+        assert!(stats.first_move_cutoffs <= stats.cutoffs);
+        assert!(stats.tt_hits + stats.tt_misses <= stats.nodes);
+        assert!(stats.effective_branching_factor() >= 1.0);
+
+        let mut total = stats.clone();
+        total.add(&stats);
+        assert_eq!(total.nodes, 2 * stats.nodes);
+        assert_eq!(total.nodes_per_depth[1], 2 * stats.nodes_per_depth[1]);
+        assert_eq!(
+            total.effective_branching_factor(),
+            stats.effective_branching_factor()
+        );
+    }
+
+    #[test]
+    fn test_solve_weak_terminal() {
+        let mut solver = Solver::new();
+        // the first player can complete four in the first column
+        assert_eq!(solver.solve_weak("121212".parse().unwrap()), Outcome::Win);
+        // and has done it
+        assert_eq!(solver.solve_weak("1212121".parse().unwrap()), Outcome::Loss);
+        // a drawn game
+        let full: Position = "547125662261271266215743771576315353334444"
+            .parse()
+            .unwrap();
+        assert_eq!(solver.solve_weak(full), Outcome::Draw);
+    }
+}
@@ -0,0 +1,220 @@
+// Endgame tablebase: exact scores of the positions with few empty cells
+//
+// Enumerating every position with N empty cells from the empty board is out of reach for a 7x6
+// board, so the table holds the positions with at most `max_empty` empty cells reachable from
+// some root positions, e.g. the ones of a data set. They are scored by retrograde analysis:
+// ply by ply starting from the full board, each score is computed from the scores of the
+// positions one ply later. The positions between a root and `max_empty` empty cells are
+// enumerated too, so the roots can have at most `MAX_ROOT_EXTRA_EMPTY` more empty cells.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+
+use crate::position::{Position, HEIGHT, WIDTH};
+use crate::score::Score;
+
+// Each extra empty cell of a root multiplies the positions to enumerate by up to WIDTH
+pub const MAX_ROOT_EXTRA_EMPTY: u64 = 4;
+
+pub struct Tablebase {
+    max_empty: u64,
+    /// sorted by key, `symmetric_key << 8 | score`
+    entries: Vec<u64>,
+}
+
+impl Tablebase {
+    pub fn build(roots: &[Position], max_empty: u64) -> Result<Tablebase, String> {
+        let size = WIDTH * HEIGHT;
+        if let Some(root) = roots
+            .iter()
+            .find(|p| size - p.play_count() > max_empty + MAX_ROOT_EXTRA_EMPTY)
+        {
+            return Err(format!(
+                "{} empty cells in a root, at most {} with {} empty cells in the table",
+                size - root.play_count(),
+                max_empty + MAX_ROOT_EXTRA_EMPTY,
+                max_empty
+            ));
+        }
+        let first_ply = size.saturating_sub(max_empty) as usize;
+        let mut levels: Vec<Vec<Position>> = vec![Vec::new(); size as usize + 1];
+        let mut seen: Vec<HashSet<u64>> = vec![HashSet::new(); size as usize + 1];
+        for p in roots.iter().filter(|p| !p.is_game_over()) {
+            let ply = p.play_count() as usize;
+            if seen[ply].insert(p.symmetric_key()) {
+                levels[ply].push(*p);
+            }
+        }
+        for ply in 0..size as usize {
+            let (current, next) = levels.split_at_mut(ply + 1);
+            // the score of a position where the player to move wins is known
+            for p in current[ply].iter().filter(|p| !p.can_win_next()) {
+                for c in (0..WIDTH).filter(|&c| p.is_valid_play(c)) {
+                    let child = p.play(c);
+                    if seen[ply + 1].insert(child.symmetric_key()) {
+                        next[0].push(child);
+                    }
+                }
+            }
+            seen[ply] = HashSet::new();
+            if ply < first_ply {
+                // only needed to reach the positions of the table
+                levels[ply] = Vec::new();
+            }
+        }
+
+        let mut scores: HashMap<u64, i32> = HashMap::new();
+        for ply in (first_ply..=size as usize).rev() {
+            for p in std::mem::take(&mut levels[ply])
+                .iter()
+                .filter(|p| !p.can_win_next())
+            {
+                let score = (0..WIDTH)
+                    .filter(|&c| p.is_valid_play(c))
+                    .map(|c| {
+                        let child = p.play(c);
+                        -if child.can_win_next() {
+                            Score::win_now(child.play_count()).value()
+                        } else {
+                            scores[&child.symmetric_key()]
+                        }
+                    })
+                    .max()
+                    .unwrap_or(0); // the board is full
+                scores.insert(p.symmetric_key(), score);
+            }
+        }
+        let mut entries: Vec<u64> = scores
+            .into_iter()
+            .map(|(key, score)| key << 8 | score as i8 as u8 as u64)
+            .collect();
+        entries.sort_unstable();
+        Ok(Tablebase { max_empty, entries })
+    }
+
+    pub fn max_empty(&self) -> u64 {
+        self.max_empty
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Score of the position for the player to move,
+    // positions where the player to move can win directly are not stored
+    pub fn get(&self, p: &Position) -> Option<Score> {
+        if WIDTH * HEIGHT - p.play_count() > self.max_empty {
+            return None;
+        }
+        let key = p.symmetric_key();
+        let i = self
+            .entries
+            .binary_search_by_key(&key, |entry| entry >> 8)
+            .ok()?;
+        Some(Score::new(
+            self.entries[i] as u8 as i8 as i32,
+            p.play_count(),
+        ))
+    }
+
+    // Little endian `max_empty` followed by the entries
+    pub fn write<W: Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(&self.max_empty.to_le_bytes())?;
+        for entry in &self.entries {
+            w.write_all(&entry.to_le_bytes())?;
+        }
+        w.flush()
+    }
+
+    pub fn read<R: Read>(mut r: R) -> io::Result<Tablebase> {
+        let mut bytes = Vec::new();
+        r.read_to_end(&mut bytes)?;
+        if bytes.len() < 8 || bytes.len() % 8 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated tablebase",
+            ));
+        }
+        let mut words = bytes
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()));
+        let max_empty = words.next().unwrap();
+        let entries: Vec<u64> = words.collect();
+        if entries.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsorted tablebase",
+            ));
+        }
+        Ok(Tablebase { max_empty, entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::Solver;
+    use std::sync::Arc;
+
+    // a win for the player to move with 12 empty cells
+    const ROOT: &str = "427566236745127177115664464254";
+
+    #[test]
+    fn test_build() {
+        let root: Position = ROOT.parse().unwrap();
+        let tablebase = Tablebase::build(std::slice::from_ref(&root), 10).unwrap();
+        assert!(!tablebase.is_empty());
+        // too many empty cells
+        assert_eq!(tablebase.get(&root), None);
+        // too many positions between the root and the table
+        assert!(Tablebase::build(std::slice::from_ref(&root), 7).is_err());
+
+        let mut solver = Solver::new();
+        for c in (0..WIDTH).filter(|&c| root.is_valid_play(c)) {
+            let p = root.play(c);
+            for c in (0..WIDTH).filter(|&c| p.is_valid_play(c)) {
+                let child = p.play(c);
+                if child.is_game_over() || child.can_win_next() {
+                    assert_eq!(tablebase.get(&child), None);
+                    continue;
+                }
+                assert_eq!(tablebase.get(&child), Some(solver.solve(child)));
+                assert_eq!(tablebase.get(&child.mirror()), tablebase.get(&child));
+            }
+        }
+    }
+
+    #[test]
+    fn test_solver_probe() {
+        let root: Position = ROOT.parse().unwrap();
+        let mut solver = Solver::new();
+        let (score, stats) = solver.solve_with_stats(root);
+        assert_eq!(score.value(), 2);
+
+        let mut solver = Solver::new();
+        solver.set_tablebase(Arc::new(
+            Tablebase::build(std::slice::from_ref(&root), 10).unwrap(),
+        ));
+        let (probed, probed_stats) = solver.solve_with_stats(root);
+        assert_eq!(probed, score);
+        assert!(probed_stats.nodes < stats.nodes);
+    }
+
+    #[test]
+    fn test_read_write() {
+        let root: Position = ROOT.parse().unwrap();
+        let tablebase = Tablebase::build(&[root], 12).unwrap();
+        let mut bytes = Vec::new();
+        tablebase.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 8 * (tablebase.len() + 1));
+        let read = Tablebase::read(&bytes[..]).unwrap();
+        assert_eq!(read.max_empty(), 12);
+        assert_eq!(read.entries, tablebase.entries);
+        assert!(Tablebase::read(&bytes[..12]).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+// Threat analysis: the empty cells where a player would complete four
+//
+// Rows are counted from 1 at the bottom. With the board filling up column by column at the
+// end of the game, the first player gets the odd rows and the second player the even rows,
+// so odd threats are the useful ones for the first player and even threats for the second.
+
+use std::fmt;
+
+use crate::position::{Player, Position, FULL_HEIGHT, HEIGHT, WIDTH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Threat {
+    pub column: u64,
+    /// 0 for the bottom row
+    pub row: u64,
+    /// the cell can be played now
+    pub playable: bool,
+    /// the cell above is also a threat of the same player, the opponent can block only one
+    pub stacked: bool,
+}
+
+impl Threat {
+    pub fn is_odd(&self) -> bool {
+        self.row.is_multiple_of(2)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThreatAnalysis {
+    /// ordered by column then row
+    pub first: Vec<Threat>,
+    pub second: Vec<Threat>,
+    /// the player who wins if the rest of the board is filled column by column
+    pub zugzwang: Player,
+}
+
+impl ThreatAnalysis {
+    pub fn threats(&self, player: Player) -> &[Threat] {
+        match player {
+            Player::First => &self.first,
+            Player::Second => &self.second,
+        }
+    }
+}
+
+pub fn analyze_threats(p: &Position) -> ThreatAnalysis {
+    let first = threats(p.first_player_stones(), p.mask());
+    let second = threats(p.second_player_stones(), p.mask());
+    // Simplified zugzwang rules: the first player controls the zugzwang with an odd threat
+    // that is reached before any threat of the second player in its column, otherwise the
+    // second player keeps it by answering in the column the first player just played
+    let lowest_second = |column| {
+        second
+            .iter()
+            .filter(|t| t.column == column)
+            .map(|t| t.row)
+            .min()
+    };
+    let zugzwang = if first
+        .iter()
+        .any(|t| t.is_odd() && lowest_second(t.column).is_none_or(|row| t.row < row))
+    {
+        Player::First
+    } else {
+        Player::Second
+    };
+    ThreatAnalysis {
+        first,
+        second,
+        zugzwang,
+    }
+}
+
+fn threats(stones: u64, mask: u64) -> Vec<Threat> {
+    let cells = Position::winning_cells(stones, mask);
+    let mut threats = Vec::new();
+    for column in 0..WIDTH {
+        for row in 0..HEIGHT {
+            let cell = 1 << (column * FULL_HEIGHT + row);
+            if cells & cell == 0 {
+                continue;
+            }
+            threats.push(Threat {
+                column,
+                row,
+                playable: row == 0 || mask & (cell >> 1) != 0,
+                stacked: cells & (cell << 1) != 0,
+            });
+        }
+    }
+    threats
+}
+
+// One line per threat, for example "first player: odd threat in column 4, row 3 (stacked)"
+impl fmt::Display for ThreatAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &player in &[Player::First, Player::Second] {
+            for t in self.threats(player) {
+                write!(
+                    f,
+                    "{}: {} threat in column {}, row {}",
+                    player,
+                    if t.is_odd() { "odd" } else { "even" },
+                    t.column + 1,
+                    t.row + 1
+                )?;
+                match (t.playable, t.stacked) {
+                    (true, true) => writeln!(f, " (playable, stacked)")?,
+                    (true, false) => writeln!(f, " (playable)")?,
+                    (false, true) => writeln!(f, " (stacked)")?,
+                    (false, false) => writeln!(f)?,
+                }
+            }
+        }
+        writeln!(f, "the {} controls the zugzwang", self.zugzwang)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_odd_threats() {
+        let p: Position = "334455".parse().unwrap();
+        let analysis = analyze_threats(&p);
+        assert_eq!(
+            analysis.first,
+            vec![
+                Threat {
+                    column: 1,
+                    row: 0,
+                    playable: true,
+                    stacked: false
+                },
+                Threat {
+                    column: 5,
+                    row: 0,
+                    playable: true,
+                    stacked: false
+                }
+            ]
+        );
+        assert!(analysis.first.iter().all(Threat::is_odd));
+        assert_eq!(analysis.second.len(), 2);
+        assert!(analysis.second.iter().all(|t| !t.is_odd() && !t.playable));
+        assert_eq!(analysis.zugzwang, Player::First);
+        assert!(analysis
+            .to_string()
+            .starts_with("first player: odd threat in column 2, row 1 (playable)\n"));
+    }
+
+    #[test]
+    fn test_lower_threat_wins_the_column() {
+        let p =
+            Position::from_board_string("......./......./......./111..../222..../12112..").unwrap();
+        let analysis = analyze_threats(&p);
+        assert_eq!(analysis.first.len(), 1);
+        assert_eq!((analysis.first[0].column, analysis.first[0].row), (3, 2));
+        assert!(!analysis.first[0].playable);
+        assert_eq!(analysis.second.len(), 1);
+        assert_eq!((analysis.second[0].column, analysis.second[0].row), (3, 1));
+        assert!(analysis.second[0].playable);
+        assert_eq!(analysis.zugzwang, Player::Second);
+    }
+
+    #[test]
+    fn test_stacked() {
+        let p =
+            Position::from_board_string("......./......./......./......./111.22./111.222").unwrap();
+        let analysis = analyze_threats(&p);
+        let stacked: Vec<(u64, u64)> = analysis
+            .first
+            .iter()
+            .filter(|t| t.stacked)
+            .map(|t| (t.column, t.row))
+            .collect();
+        assert_eq!(stacked, vec![(3, 0)]);
+        assert!(analysis.second.iter().all(|t| !t.stacked));
+    }
+}
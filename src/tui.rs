@@ -0,0 +1,431 @@
+// Full screen terminal UI to play against the solver
+//
+// keys:
+//   left/right, 1-7     select a column
+//   enter, space, down  play in the selected column
+//   u                   undo the last move (and the AI answer)
+//   h                   hint, highlight the best column
+//   s                   swap sides with the AI
+//   n                   new game
+//   q, esc              quit
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use crate::position::{moves_to_string, Position, HEIGHT, WIDTH};
+use crate::score::Score;
+use crate::solver::{Solver, COLUMNS_ORDER};
+
+// The AI plays the best column found so far after this delay
+const AI_THINK_TIME: Duration = Duration::from_secs(3);
+const BAR_WIDTH: i32 = 21;
+const FIRST_COLOR: Color = Color::Red;
+const SECOND_COLOR: Color = Color::Yellow;
+
+pub fn run() -> io::Result<()> {
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(out, EnterAlternateScreen, Hide)?;
+    let result = event_loop(&mut out);
+    execute!(out, Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn event_loop<W: Write>(out: &mut W) -> io::Result<()> {
+    let mut game = Game::new();
+    let mut evaluator = Evaluator::new();
+    evaluator.evaluate(&game.position);
+    let mut redraw = true;
+    loop {
+        for (c, score) in evaluator.poll() {
+            game.scores[c as usize] = Some(score);
+            redraw = true;
+        }
+        if !game.is_over()
+            && !game.human_to_move()
+            && (game.is_evaluated() || game.changed.elapsed() > AI_THINK_TIME)
+        {
+            let c = game.best_column().unwrap();
+            game.play(c);
+            evaluator.evaluate(&game.position);
+            redraw = true;
+        }
+        if redraw {
+            draw(out, &game)?;
+            redraw = false;
+        }
+
+        if !event::poll(Duration::from_millis(50))? {
+            continue;
+        }
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        redraw = true;
+        let changed = match key.code {
+            KeyCode::Left => {
+                game.cursor = game.cursor.saturating_sub(1);
+                false
+            }
+            KeyCode::Right => {
+                game.cursor = (game.cursor + 1).min(WIDTH - 1);
+                false
+            }
+            KeyCode::Char(c @ '1'..='7') => {
+                game.cursor = c as u64 - '1' as u64;
+                false
+            }
+            KeyCode::Enter | KeyCode::Down | KeyCode::Char(' ') => {
+                game.human_to_move() && game.play(game.cursor)
+            }
+            KeyCode::Char('u') => game.undo(),
+            KeyCode::Char('h') => {
+                game.hint = true;
+                false
+            }
+            KeyCode::Char('s') => {
+                game.human_first = !game.human_first;
+                false
+            }
+            KeyCode::Char('n') => {
+                game = Game {
+                    human_first: game.human_first,
+                    ..Game::new()
+                };
+                true
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            _ => false,
+        };
+        if changed {
+            evaluator.evaluate(&game.position);
+        }
+    }
+}
+
+struct Game {
+    moves: Vec<u64>,
+    position: Position,
+    cursor: u64,
+    human_first: bool,
+    hint: bool,
+    // scores of the current position, filled as the evaluator finds them
+    scores: [Option<Score>; WIDTH as usize],
+    changed: Instant,
+}
+
+impl Game {
+    fn new() -> Game {
+        Game {
+            moves: Vec::new(),
+            position: Position::new(),
+            cursor: WIDTH / 2,
+            human_first: true,
+            hint: false,
+            scores: [None; WIDTH as usize],
+            changed: Instant::now(),
+        }
+    }
+
+    fn play(&mut self, col_pos: u64) -> bool {
+        if self.is_over() || !self.position.is_valid_play(col_pos) {
+            return false;
+        }
+        self.moves.push(col_pos);
+        self.set_position(self.position.play(col_pos));
+        true
+    }
+
+    // Take back moves until it's the human turn again
+    fn undo(&mut self) -> bool {
+        if self.moves.pop().is_none() {
+            return false;
+        }
+        // the moves were already played
+        self.set_position(Position::try_from(&self.moves[..]).unwrap());
+        if !self.human_to_move() && self.moves.pop().is_some() {
+            self.set_position(Position::try_from(&self.moves[..]).unwrap());
+        }
+        true
+    }
+
+    fn set_position(&mut self, position: Position) {
+        self.position = position;
+        self.scores = [None; WIDTH as usize];
+        self.hint = false;
+        self.changed = Instant::now();
+    }
+
+    fn first_to_move(&self) -> bool {
+        self.position.play_count().is_multiple_of(2)
+    }
+
+    fn human_to_move(&self) -> bool {
+        self.first_to_move() == self.human_first
+    }
+
+    fn is_won(&self) -> bool {
+        self.position.opponent().is_winning()
+    }
+
+    fn is_over(&self) -> bool {
+        self.is_won() || self.position.play_count() == WIDTH * HEIGHT
+    }
+
+    fn is_evaluated(&self) -> bool {
+        (0..WIDTH).all(|c| !self.position.is_valid_play(c) || self.scores[c as usize].is_some())
+    }
+
+    // Best evaluated column, the most central one if nothing is evaluated yet
+    fn best_column(&self) -> Option<u64> {
+        let valid = COLUMNS_ORDER
+            .iter()
+            .copied()
+            .filter(|&c| self.position.is_valid_play(c));
+        valid
+            .clone()
+            .rev()
+            .filter_map(|c| self.scores[c as usize].map(|s| (c, s)))
+            .max_by_key(|&(_, s)| s.value())
+            .map(|(c, _)| c)
+            .or_else(|| valid.clone().next())
+    }
+}
+
+// Solve each column of a position in a background thread
+struct Evaluator {
+    requests: Sender<(Position, Arc<AtomicBool>, u64)>,
+    results: Receiver<(u64, u64, Score)>,
+    stop: Arc<AtomicBool>,
+    generation: u64,
+}
+
+impl Evaluator {
+    fn new() -> Evaluator {
+        let (requests, requests_rx) = mpsc::channel::<(Position, Arc<AtomicBool>, u64)>();
+        let (results_tx, results) = mpsc::channel();
+        thread::spawn(move || {
+            let mut solver = Solver::new();
+            for (p, stop, generation) in requests_rx {
+                solver.set_stop_flag(stop);
+                for c in COLUMNS_ORDER
+                    .iter()
+                    .copied()
+                    .filter(|&c| p.is_valid_play(c))
+                {
+                    let score = solver.solve_play(&p, c);
+                    if solver.stopped() {
+                        break;
+                    }
+                    if results_tx.send((generation, c, score)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Evaluator {
+            requests,
+            results,
+            stop: Arc::new(AtomicBool::new(false)),
+            generation: 0,
+        }
+    }
+
+    // Abort the current evaluation and start evaluating `p`
+    fn evaluate(&mut self, p: &Position) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.stop = Arc::new(AtomicBool::new(false));
+        self.generation += 1;
+        if !p.opponent().is_winning() {
+            self.requests
+                .send((*p, self.stop.clone(), self.generation))
+                .unwrap();
+        }
+    }
+
+    // Scores found since the last call for the current position
+    fn poll(&self) -> Vec<(u64, Score)> {
+        self.results
+            .try_iter()
+            .filter(|&(generation, _, _)| generation == self.generation)
+            .map(|(_, c, score)| (c, score))
+            .collect()
+    }
+}
+
+fn draw<W: Write>(out: &mut W, game: &Game) -> io::Result<()> {
+    let (human_color, ai_color) = if game.human_first {
+        (FIRST_COLOR, SECOND_COLOR)
+    } else {
+        (SECOND_COLOR, FIRST_COLOR)
+    };
+    queue!(out, MoveTo(0, 0), Clear(ClearType::All))?;
+    queue!(
+        out,
+        Print(" connect4    you: "),
+        SetForegroundColor(human_color),
+        Print("●"),
+        ResetColor,
+        Print("   AI: "),
+        SetForegroundColor(ai_color),
+        Print("●"),
+        ResetColor,
+    )?;
+
+    // cursor and hint above the board
+    queue!(out, MoveTo(1, 2))?;
+    for c in 0..WIDTH {
+        let marker = if game.hint && game.best_column() == Some(c) {
+            "?"
+        } else if c == game.cursor {
+            "v"
+        } else {
+            " "
+        };
+        queue!(out, Print(format!("{} ", marker)))?;
+    }
+
+    let first_stones = game.position.first_player_stones();
+    for y in 0..HEIGHT {
+        queue!(out, MoveTo(1, (3 + HEIGHT - 1 - y) as u16))?;
+        for x in 0..WIDTH {
+            let cell = 1 << (x * (HEIGHT + 1) + y);
+            if game.position.mask() & cell == 0 {
+                queue!(out, Print("· "))?;
+            } else {
+                let color = if first_stones & cell != 0 {
+                    FIRST_COLOR
+                } else {
+                    SECOND_COLOR
+                };
+                queue!(out, SetForegroundColor(color), Print("● "), ResetColor)?;
+            }
+        }
+    }
+    queue!(
+        out,
+        MoveTo(1, (3 + HEIGHT) as u16),
+        Print("1 2 3 4 5 6 7"),
+        MoveTo(18, 3),
+        Print(format!("moves: {}", moves_to_string(&game.moves)))
+    )?;
+
+    let status = if game.is_won() {
+        if game.human_to_move() {
+            "the AI wins"
+        } else {
+            "you win"
+        }
+    } else if game.is_over() {
+        "draw"
+    } else if game.human_to_move() {
+        "your turn"
+    } else {
+        "the AI is thinking..."
+    };
+    queue!(out, MoveTo(18, 5), Print(status))?;
+
+    // evaluation bars from the point of view of the player to move
+    let bars_top = 5 + HEIGHT as u16;
+    queue!(out, MoveTo(1, bars_top), Print("evaluation"))?;
+    let max_score = Score::win_now(0).value();
+    for c in 0..WIDTH {
+        queue!(
+            out,
+            MoveTo(1, bars_top + 1 + c as u16),
+            Print(format!("{} ", c + 1))
+        )?;
+        let score = match game.scores[c as usize] {
+            Some(score) => score,
+            None => continue,
+        };
+        let filled = ((score.value() + max_score) * BAR_WIDTH / (2 * max_score)) as usize;
+        let color = if score.is_win() {
+            Color::Green
+        } else if score.is_draw() {
+            Color::Grey
+        } else {
+            Color::DarkRed
+        };
+        queue!(
+            out,
+            SetForegroundColor(color),
+            Print("█".repeat(filled)),
+            Print("░".repeat(BAR_WIDTH as usize - filled)),
+            ResetColor,
+            Print(format!(" {}", score))
+        )?;
+    }
+    queue!(
+        out,
+        MoveTo(1, bars_top + 2 + WIDTH as u16),
+        Print("←/→ select  enter play  u undo  h hint  s swap  n new  q quit")
+    )?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_play_undo() {
+        let mut game = Game::new();
+        assert!(game.human_to_move());
+        assert!(game.play(3));
+        assert!(!game.human_to_move());
+        assert!(game.play(3));
+        assert!(game.human_to_move());
+        assert!(game.undo());
+        assert!(game.human_to_move());
+        assert!(game.moves.is_empty());
+        assert!(!game.undo());
+
+        for _ in 0..HEIGHT {
+            game.play(0);
+        }
+        assert!(!game.play(0));
+        // odd number of moves, the human is the second player
+        game.human_first = false;
+        assert!(game.undo());
+        assert_eq!(game.moves.len(), HEIGHT as usize - 1);
+        assert!(game.human_to_move());
+    }
+
+    #[test]
+    fn test_game_over() {
+        let mut game = Game::new();
+        for &c in &[0, 1, 0, 1, 0, 1] {
+            game.play(c);
+        }
+        assert!(!game.is_over());
+        assert!(game.play(0));
+        assert!(game.is_won());
+        assert!(!game.play(2));
+    }
+
+    #[test]
+    fn test_best_column() {
+        let mut game = Game::new();
+        assert_eq!(game.best_column(), Some(3));
+        game.scores[5] = Some(Score::new(-2, 0));
+        assert_eq!(game.best_column(), Some(5));
+        game.scores[1] = Some(Score::new(1, 0));
+        game.scores[2] = Some(Score::new(1, 0));
+        assert_eq!(game.best_column(), Some(2));
+        assert!(!game.is_evaluated());
+    }
+}